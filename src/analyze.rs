@@ -0,0 +1,692 @@
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+use crate::chunk::ChunkRef;
+use crate::chunk_type::ChunkType;
+use crate::image_header::{ColorType, ImageHeader, InterlaceMethod};
+use crate::pixels;
+use crate::png::validate_header;
+use crate::text::TextChunk;
+use crate::{compress, crypto, fragment, stego, validate, Result};
+
+/// Text longer than this is reported; metadata rarely runs past a few lines.
+pub const OVERSIZED_TEXT_LEN: usize = 4096;
+
+/// Most pixels decoded for LSB analysis. Larger non-interlaced images are sampled from the top rows,
+/// larger interlaced ones are skipped.
+pub const MAX_ANALYZED_PIXELS: u64 = 1 << 22;
+
+/// Registered extension chunks, which the PNG specification itself does not define.
+const EXTENSION_CHUNKS: [&str; 10] = ["oFFs", "pCAL", "sCAL", "sTER", "gIFg", "gIFx", "dSIG", "acTL", "fcTL", "fdAT"];
+
+/// Payload headers written by this crate.
+const PAYLOAD_MARKERS: [([u8; 4], &str); 4] = [
+    (crypto::MAGIC, "encrypted payload"),
+    (compress::MAGIC, "compressed payload"),
+    (fragment::MAGIC, "payload fragment"),
+    (stego::MAGIC, "pixel payload header")
+];
+
+/// Chi-square probability above which the LSB pairs of values look equalised by embedding.
+const CHI_SQUARE_THRESHOLD: f64 = 0.95;
+
+/// Prefixes of the samples checked by the chi-square attack, in twentieths.
+const CHI_SQUARE_STEPS: usize = 20;
+
+/// RS embedding rate estimates below this are within the noise of natural images.
+const RS_THRESHOLD: f64 = 0.05;
+
+/// The result of [`analyze`]: chunk statistics and suspicious regions, most suspicious first.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub chunks: Vec<ChunkSummary>,
+    pub findings: Vec<Finding>,
+    /// `None` if the pixels could not be decoded, are not suited to LSB analysis or are too many
+    /// to sample within [`MAX_ANALYZED_PIXELS`].
+    pub lsb: Option<LsbAnalysis>
+}
+
+impl Report {
+    /// Overall suspicion from 0 to 100, combining the findings' scores as independent odds.
+    pub fn score(&self) -> u8 {
+        let clean = self.findings.iter().fold(1.0, |clean, f| clean * (1.0 - f.score as f64 / 100.0));
+        ((1.0 - clean) * 100.0).round() as u8
+    }
+
+    pub fn verdict(&self) -> &'static str {
+        match self.score() {
+            0..=24 => "clean",
+            25..=59 => "suspicious",
+            _ => "likely hidden data"
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkSummary {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    /// Byte offset of the chunk's length field.
+    pub offset: usize,
+    pub length: usize,
+    /// Shannon entropy of the chunk data, in bits per byte.
+    pub entropy: f64,
+    pub crc_ok: bool
+}
+
+/// Something that may hide data, scored from 0 to 100.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub score: u8,
+    pub region: Region,
+    pub kind: FindingKind
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{:>3}] {}: {}", self.score, self.region, self.kind)
+    }
+}
+
+/// Where a finding is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    Chunk { index: usize, chunk_type: ChunkType, offset: usize },
+    /// A byte range of the file.
+    Bytes(Range<usize>),
+    /// A range of colour samples in raster order, as sequential LSB embedding fills them.
+    Samples { range: Range<usize>, total: usize },
+    Pixels
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Region::Chunk { index, chunk_type, offset } => write!(f, "chunk {index} ({chunk_type}) at byte {offset}"),
+            Region::Bytes(range) => write!(f, "bytes {}..{}", range.start, range.end),
+            Region::Samples { range, total } => {
+                let percent = |n: usize| n as f64 * 100.0 / *total as f64;
+                write!(f, "samples {}..{} ({:.0}%-{:.0}% of the image)", range.start, range.end, percent(range.start), percent(range.end))
+            }
+            Region::Pixels => write!(f, "pixels")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum FindingKind {
+    /// An ancillary chunk type reserved for private use.
+    PrivateChunk,
+    /// A public chunk type that no specification defines.
+    UnknownChunk,
+    CrcMismatch,
+    /// Chunk data starting with a header this crate writes.
+    PayloadMarker(&'static str),
+    OversizedText { keyword: String, length: usize },
+    /// Data that looks encrypted or compressed where plain data is expected.
+    HighEntropy { entropy: f64 },
+    /// Bytes after the IEND chunk, which decoders ignore.
+    TrailingData { entropy: f64 },
+    /// Bytes that do not parse as chunks. Holds the parse error.
+    Unparseable(String),
+    MissingIend,
+    /// The chi-square attack found the pairs of values differing only in their LSB equalised.
+    LsbChiSquare { probability: f64 },
+    /// RS analysis estimated this fraction of the colour samples to carry a payload.
+    LsbRs { rate: f64 }
+}
+
+impl Display for FindingKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FindingKind::PrivateChunk => write!(f, "private ancillary chunk"),
+            FindingKind::UnknownChunk => write!(f, "unknown public chunk"),
+            FindingKind::CrcMismatch => write!(f, "CRC does not match the data"),
+            FindingKind::PayloadMarker(marker) => write!(f, "starts with a pngcoder {marker} header"),
+            FindingKind::OversizedText { keyword, length } => write!(f, "{length} bytes of text under \"{keyword}\""),
+            FindingKind::HighEntropy { entropy } => write!(f, "high entropy: {entropy:.2} bits/byte"),
+            FindingKind::TrailingData { entropy } => write!(f, "data after IEND, entropy {entropy:.2} bits/byte"),
+            FindingKind::Unparseable(reason) => write!(f, "does not parse as chunks: {reason}"),
+            FindingKind::MissingIend => write!(f, "no IEND chunk"),
+            FindingKind::LsbChiSquare { probability } => write!(f, "chi-square embedding probability {probability:.3}"),
+            FindingKind::LsbRs { rate } => write!(f, "RS analysis estimates {:.1}% of samples carry a payload", rate * 100.0)
+        }
+    }
+}
+
+/// Statistics on the least significant bits of the colour samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LsbAnalysis {
+    /// Colour samples analysed, alpha excluded.
+    pub samples: usize,
+    /// Rows analysed from the top of the image, fewer than `height` above [`MAX_ANALYZED_PIXELS`].
+    pub rows: usize,
+    pub height: usize,
+    /// Chi-square embedding probability over every sample.
+    pub chi_square: f64,
+    /// Longest run of samples from the start of the image over which the chi-square
+    /// probability is above the threshold, as left by sequential embedding.
+    pub chi_square_prefix: usize,
+    /// Chi-square embedding probability over that prefix.
+    pub prefix_probability: f64,
+    /// RS estimate of the embedded fraction, per colour channel.
+    pub rs_rates: Vec<f64>
+}
+
+impl LsbAnalysis {
+    /// Mean RS estimate over the colour channels.
+    pub fn rs_rate(&self) -> f64 {
+        self.rs_rates.iter().sum::<f64>() / self.rs_rates.len().max(1) as f64
+    }
+}
+
+/// Looks for data hidden in a PNG file: unusual chunks, data after IEND, oversized or
+/// high-entropy text and statistical traces of LSB embedding in the pixels.
+///
+/// Only the PNG signature must be valid; damaged files are analysed as far as they parse.
+pub fn analyze(bytes: &[u8]) -> Result<Report> {
+    validate_header(bytes)?;
+
+    let mut chunks: Vec<(usize, ChunkRef)> = Vec::new();
+    let mut findings = Vec::new();
+    let mut cursor = 8;
+    let mut iend = false;
+    while cursor < bytes.len() && !iend {
+        match ChunkRef::scan(&bytes[cursor..]) {
+            Ok(chunk) => {
+                let length = chunk.data().len();
+                iend = chunk.chunk_type().to_string() == "IEND";
+                chunks.push((cursor, chunk));
+                cursor += 12 + length;
+            }
+            Err(e) => {
+                let reason = std::error::Error::source(&e).map_or_else(|| e.to_string(), ToString::to_string);
+                findings.push(Finding { score: 60, region: Region::Bytes(cursor..bytes.len()), kind: FindingKind::Unparseable(reason) });
+                cursor = bytes.len();
+            }
+        }
+    }
+
+    if cursor < bytes.len() {
+        let trailing = &bytes[cursor..];
+        findings.push(Finding {
+            score: 80,
+            region: Region::Bytes(cursor..bytes.len()),
+            kind: FindingKind::TrailingData { entropy: entropy(trailing) }
+        });
+    } else if !iend && findings.is_empty() {
+        findings.push(Finding { score: 20, region: Region::Bytes(bytes.len()..bytes.len()), kind: FindingKind::MissingIend });
+    }
+
+    let mut summaries = Vec::with_capacity(chunks.len());
+    for (index, (offset, chunk)) in chunks.iter().enumerate() {
+        let summary = ChunkSummary {
+            index,
+            chunk_type: chunk.chunk_type().clone(),
+            offset: *offset,
+            length: chunk.data().len(),
+            entropy: entropy(chunk.data()),
+            crc_ok: chunk.verify().is_ok()
+        };
+        findings.extend(chunk_findings(chunk, &summary));
+        summaries.push(summary);
+    }
+
+    let lsb = lsb_analysis(&chunks);
+    if let Some(lsb) = &lsb {
+        if lsb.chi_square_prefix > 0 {
+            findings.push(Finding {
+                score: 70,
+                region: Region::Samples { range: 0..lsb.chi_square_prefix, total: lsb.samples },
+                kind: FindingKind::LsbChiSquare { probability: lsb.prefix_probability }
+            });
+        }
+        let rate = lsb.rs_rate();
+        if rate >= RS_THRESHOLD {
+            findings.push(Finding { score: (rate * 150.0).min(90.0) as u8, region: Region::Pixels, kind: FindingKind::LsbRs { rate } });
+        }
+    }
+
+    findings.sort_by_key(|f| std::cmp::Reverse(f.score));
+    Ok(Report { chunks: summaries, findings, lsb })
+}
+
+fn chunk_findings(chunk: &ChunkRef, summary: &ChunkSummary) -> Vec<Finding> {
+    let chunk_type = chunk.chunk_type();
+    let name = chunk_type.to_string();
+    let region = Region::Chunk { index: summary.index, chunk_type: chunk_type.clone(), offset: summary.offset };
+    let finding = |score: u8, kind: FindingKind| Finding { score, region: region.clone(), kind };
+    let mut findings = Vec::new();
+
+    let known = validate::is_known(&name) || EXTENSION_CHUNKS.contains(&name.as_str());
+    if !chunk_type.is_public() {
+        findings.push(finding(40, FindingKind::PrivateChunk));
+    } else if !known {
+        findings.push(finding(30, FindingKind::UnknownChunk));
+    }
+    if !summary.crc_ok {
+        findings.push(finding(20, FindingKind::CrcMismatch));
+    }
+    if let Some((_, marker)) = PAYLOAD_MARKERS.iter().find(|(magic, _)| chunk.data().starts_with(magic)) {
+        findings.push(finding(90, FindingKind::PayloadMarker(marker)));
+    }
+
+    // Text is judged on its decompressed value. Other known chunks are often compressed
+    let value = match TextChunk::from_data(chunk_type, chunk.data()) {
+        Ok(text) => {
+            if text.text_value().len() > OVERSIZED_TEXT_LEN {
+                let kind = FindingKind::OversizedText { keyword: text.keyword().to_string(), length: text.text_value().len() };
+                findings.push(finding(30, kind));
+            }
+            Some(text.text_value().as_bytes().to_vec())
+        }
+        Err(_) if known => None,
+        Err(_) => Some(chunk.data().to_vec())
+    };
+    if let Some(value) = value.filter(|v| looks_random(v)) {
+        findings.push(finding(30, FindingKind::HighEntropy { entropy: entropy(&value) }));
+    }
+    findings
+}
+
+/// Shannon entropy in bits per byte.
+pub fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() { return 0.0 }
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Whether `data` is close to the highest entropy its length allows, as encrypted or
+/// compressed data is. Short data cannot reach 8 bits per byte, so the bound scales with it.
+fn looks_random(data: &[u8]) -> bool {
+    data.len() >= 32 && entropy(data) >= 0.9 * (data.len().min(256) as f64).log2()
+}
+
+fn lsb_analysis(chunks: &[(usize, ChunkRef)]) -> Option<LsbAnalysis> {
+    let (_, ihdr) = chunks.first().filter(|(_, c)| c.chunk_type().to_string() == "IHDR")?;
+    let header = ImageHeader::try_from(ihdr.data()).ok()?;
+    if header.bit_depth() < 8 || header.color_type() == ColorType::Indexed { return None }
+
+    let (width, height) = (header.width() as u64, header.height() as u64);
+    let rows = height.min(MAX_ANALYZED_PIXELS / width);
+    if rows == 0 || (rows < height && header.interlace_method() == InterlaceMethod::Adam7) { return None }
+
+    let mut compressed = Vec::new();
+    for (_, chunk) in chunks.iter().filter(|(_, c)| c.chunk_type().to_string() == "IDAT") {
+        compressed.extend_from_slice(chunk.data());
+    }
+    let pixels = if rows < height {
+        pixels::decode_rows(&header, &compressed, rows as u32).ok()?
+    } else {
+        pixels::decode(&header, &compressed).ok()?
+    };
+
+    let channels = header.color_type().channels() as usize;
+    let color_channels = if matches!(header.color_type(), ColorType::GrayscaleAlpha | ColorType::Rgba) { channels - 1 } else { channels };
+    let sample_len = header.bit_depth() as usize / 8;
+    let samples = pixels.width() * pixels.height() * color_channels;
+    if samples == 0 { return None }
+
+    // One pass over the rows, without widening the samples into copies of the image
+    let mut chi_square = ChiSquareScan::new(samples);
+    let mut rs = vec![RsScan::default(); color_channels];
+    let mut groups = vec![[0i32; 4]; color_channels];
+    for y in 0..pixels.height() {
+        let row = pixels.row(y);
+        for x in 0..pixels.width() {
+            for c in 0..color_channels {
+                let at = (x * channels + c) * sample_len;
+                let value = if sample_len == 2 { u16::from_be_bytes([row[at], row[at + 1]]) } else { row[at] as u16 };
+                chi_square.push(value);
+                groups[c][x % 4] = value as i32;
+                if x % 4 == 3 {
+                    rs[c].push(groups[c]);
+                }
+            }
+        }
+    }
+
+    let (chi_square, chi_square_prefix, prefix_probability) = chi_square.finish();
+    let rs_rates = rs.iter().map(RsScan::rate).collect();
+    Some(LsbAnalysis {
+        samples,
+        rows: pixels.height(),
+        height: height as usize,
+        chi_square,
+        chi_square_prefix,
+        prefix_probability,
+        rs_rates
+    })
+}
+
+/// The chi-square attack over a stream of samples, evaluated at every twentieth of `total`.
+///
+/// Gives the probability over every sample, and the longest prefix of the samples over which it
+/// is above [`CHI_SQUARE_THRESHOLD`] with the probability there.
+struct ChiSquareScan {
+    histogram: [usize; 256],
+    total: usize,
+    seen: usize,
+    step: usize,
+    probability: f64,
+    prefix: (usize, f64)
+}
+
+impl ChiSquareScan {
+    fn new(total: usize) -> Self {
+        let mut scan = ChiSquareScan { histogram: [0; 256], total, seen: 0, step: 1, probability: 0.0, prefix: (0, 0.0) };
+        scan.evaluate_steps();
+        scan
+    }
+
+    fn push(&mut self, sample: u16) {
+        self.histogram[(sample & 0xFF) as usize] += 1;
+        self.seen += 1;
+        self.evaluate_steps();
+    }
+
+    /// Evaluates every step whose end has been reached, several at once when they share an end.
+    fn evaluate_steps(&mut self) {
+        while self.step <= CHI_SQUARE_STEPS && self.seen == self.total * self.step / CHI_SQUARE_STEPS {
+            self.probability = chi_square_probability(&self.histogram);
+            if self.probability > CHI_SQUARE_THRESHOLD {
+                self.prefix = (self.seen, self.probability);
+            }
+            self.step += 1;
+        }
+    }
+
+    fn finish(self) -> (f64, usize, f64) {
+        (self.probability, self.prefix.0, self.prefix.1)
+    }
+}
+
+/// Westfeld and Pfitzmann's attack: LSB replacement evens out the counts of each pair of
+/// values 2k and 2k+1, which this measures as the probability of a chi-square fit.
+fn chi_square_probability(histogram: &[usize; 256]) -> f64 {
+    let mut statistic = 0.0;
+    let mut pairs = 0;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        // Sparse pairs make the statistic unreliable
+        if expected < 5.0 { continue }
+        statistic += (pair[0] as f64 - expected).powi(2) / expected;
+        pairs += 1;
+    }
+    if pairs < 2 { return 0.0 }
+    upper_gamma((pairs - 1) as f64 / 2.0, statistic / 2.0)
+}
+
+/// Fridrich's RS analysis of one colour channel, fed groups of four horizontally adjacent samples.
+///
+/// Counts regular and singular groups under the mask [0, 1, 1, 0] and its negation, over the
+/// samples and over the same samples with their LSBs flipped.
+#[derive(Debug, Clone, Default)]
+struct RsScan {
+    counts: [usize; 4],
+    flipped: [usize; 4],
+    groups: usize
+}
+
+impl RsScan {
+    fn push(&mut self, group: [i32; 4]) {
+        count_group(group, &mut self.counts);
+        count_group(group.map(|v| v ^ 1), &mut self.flipped);
+        self.groups += 1;
+    }
+
+    /// The estimated fraction of samples carrying a payload, from 0 to 1.
+    fn rate(&self) -> f64 {
+        let groups = self.groups.max(1) as f64;
+        let [r_m, s_m, r_n, s_n] = self.counts.map(|n| n as f64 / groups);
+        let [r_m1, s_m1, r_n1, s_n1] = self.flipped.map(|n| n as f64 / groups);
+
+        let d0 = r_m - s_m;
+        let d1 = r_m1 - s_m1;
+        let n0 = r_n - s_n;
+        let n1 = r_n1 - s_n1;
+        let a = 2.0 * (d1 + d0);
+        let b = n0 - n1 - d1 - 3.0 * d0;
+        let c = d0 - n0;
+
+        let z = if a.abs() < 1e-12 {
+            if b.abs() < 1e-12 { return 0.0 }
+            -c / b
+        } else {
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant < 0.0 { return 0.0 }
+            let root = discriminant.sqrt();
+            let (z1, z2) = ((-b + root) / (2.0 * a), (-b - root) / (2.0 * a));
+            if z1.abs() <= z2.abs() { z1 } else { z2 }
+        };
+        let rate = z / (z - 0.5);
+        if rate.is_finite() { rate.clamp(0.0, 1.0) } else { 0.0 }
+    }
+}
+
+/// Adds a group to the regular and singular counts under the mask and under its negation.
+fn count_group(group: [i32; 4], counts: &mut [usize; 4]) {
+    const MASK: [bool; 4] = [false, true, true, false];
+    let smoothness = |g: &[i32; 4]| g.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<i32>();
+    let original = smoothness(&group);
+    let mut positive = group;
+    let mut negative = group;
+    for i in 0..4 {
+        if MASK[i] {
+            positive[i] ^= 1;
+            negative[i] = ((negative[i] + 1) ^ 1) - 1;
+        }
+    }
+
+    match smoothness(&positive).cmp(&original) {
+        std::cmp::Ordering::Greater => counts[0] += 1,
+        std::cmp::Ordering::Less => counts[1] += 1,
+        _ => {}
+    }
+    match smoothness(&negative).cmp(&original) {
+        std::cmp::Ordering::Greater => counts[2] += 1,
+        std::cmp::Ordering::Less => counts[3] += 1,
+        _ => {}
+    }
+}
+
+/// Regularised upper incomplete gamma function Q(a, x), the chi-square survival function
+/// for `a` = degrees of freedom / 2 and `x` = statistic / 2.
+fn upper_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 { return 1.0 }
+    let log_prefix = a * x.ln() - x - ln_gamma(a);
+
+    if x < a + 1.0 {
+        // Series for the lower function P(a, x)
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut n = a;
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 { break }
+        }
+        (1.0 - sum * log_prefix.exp()).clamp(0.0, 1.0)
+    } else {
+        // Lentz's continued fraction for Q(a, x)
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny { d = tiny }
+            c = b + an / c;
+            if c.abs() < tiny { c = tiny }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 { break }
+        }
+        (log_prefix.exp() * h).clamp(0.0, 1.0)
+    }
+}
+
+/// Lanczos approximation of ln Γ(x) for x > 0.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS.iter().enumerate().fold(1.000000000190015, |sum, (i, c)| sum + c / (x + 1.0 + i as f64));
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::png::Png;
+    use std::str::FromStr;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    /// Deterministic bytes that look random to the LSB statistics.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545F4914F6CDD1Du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            })
+            .collect()
+    }
+
+    fn kinds(report: &Report) -> Vec<&FindingKind> {
+        report.findings.iter().map(|f| &f.kind).collect()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let report = analyze(DICE).unwrap();
+        assert!(report.findings.is_empty(), "{:?}", report.findings);
+        assert_eq!(report.verdict(), "clean");
+        assert_eq!(report.chunks.len(), 10);
+        assert!(report.chunks.iter().all(|c| c.crc_ok));
+
+        let lsb = report.lsb.unwrap();
+        assert_eq!(lsb.samples, 550 * 320 * 3);
+        assert!(lsb.chi_square < 0.5 && lsb.rs_rate() < RS_THRESHOLD);
+    }
+
+    #[test]
+    fn test_chunk_findings() {
+        let mut png = Png::try_from(DICE).unwrap();
+        let private = Chunk::new(ChunkType::from_str("ruSt").unwrap(), noise(500));
+        let unknown = Chunk::new(ChunkType::from_str("eXPl").unwrap(), b"plain".to_vec());
+        let marked = Chunk::new(ChunkType::from_str("ruSt").unwrap(), [&crypto::MAGIC[..], b"sealed"].concat());
+        let text = TextChunk::text("Comment", &"All work and no play. ".repeat(200)).unwrap().to_chunk().unwrap();
+        for chunk in [private, unknown, marked, text] {
+            png.insert_chunk(chunk, crate::png::ChunkPosition::BeforeIend).unwrap();
+        }
+        let mut bytes = png.as_bytes();
+        let iend_end = bytes.len();
+        bytes.extend(noise(100));
+
+        let report = analyze(&bytes).unwrap();
+        let kinds = kinds(&report);
+        assert!(matches!(kinds[0], FindingKind::PayloadMarker("encrypted payload")));
+        assert!(matches!(kinds[1], FindingKind::TrailingData { .. }));
+        assert_eq!(report.findings[1].region, Region::Bytes(iend_end..iend_end + 100));
+        assert!(kinds.contains(&&FindingKind::UnknownChunk));
+        assert_eq!(kinds.iter().filter(|k| ***k == FindingKind::PrivateChunk).count(), 2);
+        assert!(kinds.iter().any(|k| matches!(k, FindingKind::HighEntropy { .. })));
+        assert!(kinds.iter().any(|k| matches!(k, FindingKind::OversizedText { keyword, length: 4400 } if keyword == "Comment")));
+        assert_eq!(report.verdict(), "likely hidden data");
+    }
+
+    #[test]
+    fn test_damaged_file() {
+        let mut bytes = DICE.to_vec();
+        bytes[40] ^= 1;
+        bytes.truncate(20000);
+        let report = analyze(&bytes).unwrap();
+        assert!(matches!(kinds(&report)[0], FindingKind::Unparseable(_)));
+        assert!(kinds(&report).contains(&&FindingKind::CrcMismatch));
+        assert!(report.lsb.is_none());
+
+        assert!(analyze(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn test_sequential_lsb_embedding() {
+        let mut png = Png::try_from(DICE).unwrap();
+        let mut pixels = png.pixels().unwrap();
+        let options = stego::Options::default();
+        // Fills about 40% of the colour samples
+        stego::embed(&mut pixels, &ChunkType::from_str("ruSt").unwrap(), &noise(26400), &options).unwrap();
+        png.set_pixels(&pixels).unwrap();
+
+        let report = analyze(&png.as_bytes()).unwrap();
+        let lsb = report.lsb.as_ref().unwrap();
+        assert!(lsb.rs_rate() > 0.2, "{lsb:?}");
+        let samples = lsb.samples;
+        assert!(report.findings.iter().any(|f| matches!(
+            (&f.kind, &f.region),
+            (FindingKind::LsbChiSquare { .. }, Region::Samples { range, .. }) if range.end > samples / 3 && range.end < samples / 2
+        )), "{:?}", report.findings);
+    }
+
+    #[test]
+    fn test_large_image_is_sampled() {
+        let image = |width: u32, height: u32, filtered: &[u8]| {
+            let header = ImageHeader::new(width, height, 8, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+            let idat = Chunk::new(ChunkType::from_str("IDAT").unwrap(), compress::deflate(filtered).unwrap());
+            let iend = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+            Png::from_chunks(vec![header.to_chunk(), idat, iend]).as_bytes()
+        };
+
+        // Only the rows within the limit are stored, as they are all that is decoded
+        let rows = (MAX_ANALYZED_PIXELS >> 16) as usize;
+        let mut filtered = noise(((1 << 16) + 1) * rows);
+        filtered.iter_mut().step_by((1 << 16) + 1).for_each(|filter_type| *filter_type = 0);
+        let lsb = analyze(&image(1 << 16, 1 << 16, &filtered)).unwrap().lsb.unwrap();
+        assert_eq!((lsb.rows, lsb.height, lsb.samples), (rows, 1 << 16, MAX_ANALYZED_PIXELS as usize));
+
+        let report = analyze(&image(1 << 23, 1, &[0])).unwrap();
+        assert!(report.lsb.is_none());
+    }
+
+    #[test]
+    fn test_statistics() {
+        assert_eq!(entropy(b""), 0.0);
+        assert_eq!(entropy(b"aaaa"), 0.0);
+        assert_eq!(entropy(&(0..=255).collect::<Vec<u8>>()), 8.0);
+        assert!(looks_random(&noise(1000)));
+        assert!(!looks_random(&"All work and no play. ".repeat(50).into_bytes()));
+
+        // Q(1, x) = e^-x, and the chi-square median with 2 degrees of freedom is 2 ln 2
+        for x in [0.1f64, 1.0, 5.0, 20.0] {
+            assert!((upper_gamma(1.0, x) - (-x).exp()).abs() < 1e-9);
+        }
+        assert!((upper_gamma(1.0, 2f64.ln()) - 0.5).abs() < 1e-9);
+    }
+}
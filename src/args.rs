@@ -1,6 +1,10 @@
+use std::num::ParseIntError;
 use std::path::PathBuf;
-use clap::{Args, Parser, Subcommand};
-use std::ffi::OsString;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pngcoder::compress::Compression;
+use pngcoder::fragment;
+use pngcoder::parse::ParseMode;
+use pngcoder::png::ChunkPosition;
 
 #[derive(Debug, Parser)]
 #[command(name = "pngc")]
@@ -16,30 +20,276 @@ pub enum Commands {
     Decode(DecodeArgs),
     Remove(RemoveArgs),
     Print(PrintArgs),
+    /// Check chunk ordering and multiplicity against the PNG specification
+    Check(CheckArgs),
+    /// Generate an X25519 identity for --recipient and --identity, or an Ed25519 key with --signing
+    Keygen(KeygenArgs),
+    /// Sign the image data, text chunks and selected chunks with an Ed25519 key
+    Sign(SignArgs),
+    /// Check the signature and report chunks changed or added after signing
+    Verify(VerifyArgs),
+    /// Read and write tEXt, zTXt and iTXt metadata
+    #[command(subcommand)]
+    Text(TextCommands),
+    /// Look for hidden data and report suspicious regions with a score
+    Analyze(AnalyzeArgs),
+    /// Work with data appended after the IEND chunk
+    #[command(subcommand)]
+    Trailing(TrailingCommands),
+    /// Rewrite wrong CRCs, restore IHDR dimensions that do not match its CRC and fix a missing or cut-off IEND
+    Repair(RepairArgs),
+    /// Extract the PNGs embedded in a memory dump, disk image or other file
+    Carve(CarveArgs),
 }
 
 #[derive(Args, Debug)]
 pub struct EncodeArgs {
     pub file_path: PathBuf,
     pub chunk_type: String,
-    pub message: String,
-    #[arg(value_parser = clap::value_parser!(OsString), short, long)]
-    pub output_file: Option<PathBuf>
+    #[arg(required_unless_present = "input")]
+    pub message: Option<String>,
+    /// Read the payload from a file instead of MESSAGE, or from stdin if "-"
+    #[arg(short, long, conflicts_with = "message")]
+    pub input: Option<PathBuf>,
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>,
+    /// Encrypt the payload with a passphrase (read from PNGC_PASSPHRASE or prompted)
+    #[arg(long)]
+    pub encrypt: bool,
+    /// Compress the payload before any encryption: deflate (default) or zstd
+    #[arg(long, value_name = "METHOD", num_args = 0..=1, require_equals = true, default_missing_value = "deflate")]
+    pub compress: Option<Compression>,
+    /// Encrypt the payload to the public key(s) in this file. May be repeated
+    #[arg(short, long = "recipient", value_name = "FILE", conflicts_with = "encrypt")]
+    pub recipients: Vec<PathBuf>,
+    /// Split the payload across chunks holding at most this many bytes each, including a 32-byte fragment header
+    #[arg(long, value_name = "BYTES", value_parser = parse_split_size)]
+    pub split: Option<usize>,
+    /// Where to hide the payload. With lsb, CHUNK_TYPE only labels the payload
+    #[arg(long, value_enum, default_value_t = Method::Chunk)]
+    pub method: Method,
+    /// With lsb, scatter the payload in an order derived from a passphrase (read from PNGC_SCATTER_PASSPHRASE or prompted)
+    #[arg(long)]
+    pub scatter: bool,
+    /// With lsb, trade capacity for fewer changed samples: each group of 2^N - 1 samples carries N bits (1 to 8)
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub strength: u8,
+    #[arg(short, long, default_value = "before-iend")]
+    pub position: ChunkPosition
+}
+
+/// Where `encode` hides a payload.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum Method {
+    /// In a chunk of its own
+    Chunk,
+    /// In the least significant bits of the pixel samples, which survives stripping ancillary chunks
+    Lsb
 }
 
 #[derive(Args, Debug)]
 pub struct DecodeArgs {
     pub file_path: PathBuf,
-    pub chunk_type: String
+    pub chunk_type: String,
+    /// Write the payload bytes to a file, or to stdout if "-"
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Write the payload bytes to stdout without UTF-8 decoding
+    #[arg(long)]
+    pub raw: bool,
+    /// Decrypt a payload encoded with --encrypt
+    #[arg(long)]
+    pub decrypt: bool,
+    /// Decrypt a payload encoded with --recipient using the identity in this file
+    #[arg(long, value_name = "FILE", conflicts_with = "decrypt")]
+    pub identity: Option<PathBuf>,
+    /// Reassemble a payload encoded with --split from every fragment chunk of this type
+    #[arg(long)]
+    pub split: bool,
+    /// Reassemble the split payload with this message ID when the chunks hold several. Implies --split
+    #[arg(long, value_name = "ID", value_parser = parse_message_id)]
+    pub message_id: Option<u64>,
+    #[arg(long, value_enum, default_value_t = Method::Chunk)]
+    pub method: Method,
+    /// Read an lsb payload encoded with --scatter
+    #[arg(long)]
+    pub scatter: bool,
+    /// Print every chunk of this type with its position in the file
+    #[arg(short, long, conflicts_with_all = ["output", "raw", "split", "message_id", "index"])]
+    pub all: bool,
+    /// Decode the chunk at this zero-based position among chunks of this type
+    #[arg(short = 'n', long, conflicts_with_all = ["split", "message_id"])]
+    pub index: Option<usize>
 }
 
 #[derive(Args, Debug)]
 pub struct RemoveArgs {
     pub file_path: PathBuf,
-    pub chunk_type: String
+    pub chunk_type: String,
+    /// Remove every chunk of this type
+    #[arg(short, long, conflicts_with = "index")]
+    pub all: bool,
+    /// Remove the chunk at this zero-based position among chunks of this type
+    #[arg(short = 'n', long)]
+    pub index: Option<usize>
 }
 
 #[derive(Args, Debug)]
 pub struct PrintArgs {
+    pub file_path: PathBuf,
+    /// How to handle damage: strict, warn (keep chunks with bad CRCs) or recover (also truncation and garbage)
+    #[arg(long, value_name = "MODE", default_value = "strict")]
+    pub parse: ParseMode
+}
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    pub file_path: PathBuf,
+    /// How to handle damage: strict, warn (keep chunks with bad CRCs) or recover (also truncation and garbage)
+    #[arg(long, value_name = "MODE", default_value = "strict")]
+    pub parse: ParseMode
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    pub file_path: PathBuf
+}
+
+#[derive(Args, Debug)]
+pub struct CarveArgs {
+    pub input: PathBuf,
+    /// Directory to write the PNGs to, named after their byte offset in the input
+    pub output_dir: PathBuf,
+    /// Also write images that stop at a damaged chunk or at the end of the input
+    #[arg(long)]
+    pub partial: bool
+}
+
+#[derive(Args, Debug)]
+pub struct RepairArgs {
+    pub file_path: PathBuf,
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>
+}
+
+#[derive(Args, Debug)]
+pub struct KeygenArgs {
+    /// Where to write the identity. The public key is written next to it with a .pub extension
+    pub identity_file: PathBuf,
+    /// Generate an Ed25519 signing key for sign and verify instead
+    #[arg(long)]
+    pub signing: bool
+}
+
+#[derive(Args, Debug)]
+pub struct SignArgs {
+    pub file_path: PathBuf,
+    /// File holding the signing key from `keygen --signing`
+    pub key_file: PathBuf,
+    /// Also sign chunks of this type, e.g. the one holding an embedded message. May be repeated
+    #[arg(short, long = "chunk", value_name = "CHUNK_TYPE")]
+    pub chunk_types: Vec<String>,
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    pub file_path: PathBuf,
+    /// Require the signature to be made by the verifying key in this file
+    #[arg(short, long, value_name = "FILE")]
+    pub key: Option<PathBuf>
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TextCommands {
+    /// List every text chunk
+    List(TextListArgs),
+    /// Print the text stored under a keyword
+    Get(TextGetArgs),
+    /// Store text under a keyword, replacing any existing text chunks with that keyword
+    Set(TextSetArgs),
+    /// Remove every text chunk with a keyword
+    Remove(TextRemoveArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TextListArgs {
     pub file_path: PathBuf
 }
+
+#[derive(Args, Debug)]
+pub struct TextGetArgs {
+    pub file_path: PathBuf,
+    pub keyword: String
+}
+
+#[derive(Args, Debug)]
+pub struct TextSetArgs {
+    pub file_path: PathBuf,
+    pub keyword: String,
+    pub text: String,
+    /// Store compressed text in a zTXt chunk, or a compressed iTXt chunk for non-Latin-1 text
+    #[arg(short, long)]
+    pub compress: bool,
+    /// Store UTF-8 text in an iTXt chunk. Implied for text that is not Latin-1
+    #[arg(long)]
+    pub international: bool,
+    /// Language of the text, e.g. en-GB. Implies --international
+    #[arg(long, default_value = "")]
+    pub language: String,
+    /// The keyword translated to the language of the text. Implies --international
+    #[arg(long, default_value = "")]
+    pub translated_keyword: String,
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>
+}
+
+#[derive(Args, Debug)]
+pub struct TextRemoveArgs {
+    pub file_path: PathBuf,
+    pub keyword: String
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TrailingCommands {
+    /// Write the data after IEND to a file, or to stdout if "-"
+    Extract(TrailingExtractArgs),
+    /// Remove the data after IEND
+    Strip(TrailingStripArgs),
+    /// Append a file, or stdin if "-", after IEND and any existing trailing data
+    Append(TrailingAppendArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TrailingExtractArgs {
+    pub file_path: PathBuf,
+    pub output: PathBuf
+}
+
+#[derive(Args, Debug)]
+pub struct TrailingStripArgs {
+    pub file_path: PathBuf,
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>
+}
+
+#[derive(Args, Debug)]
+pub struct TrailingAppendArgs {
+    pub file_path: PathBuf,
+    pub input: PathBuf,
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>
+}
+
+fn parse_message_id(s: &str) -> Result<u64, ParseIntError> {
+    u64::from_str_radix(s, 16)
+}
+
+fn parse_split_size(s: &str) -> Result<usize, String> {
+    let size: usize = s.parse().map_err(|e: ParseIntError| e.to_string())?;
+    if size <= fragment::HEADER_LEN {
+        return Err(format!("must be larger than the {}-byte fragment header", fragment::HEADER_LEN));
+    }
+    Ok(size)
+}
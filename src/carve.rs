@@ -0,0 +1,82 @@
+use crate::chunk::ChunkRef;
+use crate::png::Png;
+
+/// A PNG found by [`carve`] inside a larger input.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Carved<'a> {
+    /// Where the signature starts in the input.
+    pub offset: usize,
+    /// The signature and every chunk whose CRC matched, up to and including IEND if it was reached.
+    pub bytes: &'a [u8],
+    pub chunks: usize,
+    /// Whether the chunks ran up to IEND. Otherwise they stop before the first damaged or cut-off chunk.
+    pub complete: bool
+}
+
+/// Finds the PNGs embedded in `data`, such as a memory dump or a raw disk image.
+///
+/// Every occurrence of [`Png::STANDARD_HEADER`] is followed chunk by chunk, checking each CRC, until IEND.
+/// With `partial`, images that stop at a damaged chunk or at the end of `data` are returned as well,
+/// as long as at least one chunk is intact. Images inside other images are found too.
+pub fn carve(data: &[u8], partial: bool) -> Vec<Carved<'_>> {
+    let signature = Png::STANDARD_HEADER;
+    let mut carved = Vec::new();
+    let mut start = 0;
+
+    while let Some(found) = data[start..].iter().position(|&b| b == signature[0]) {
+        let offset = start + found;
+        start = offset + 1;
+        if !data[offset..].starts_with(&signature) { continue }
+
+        let mut cursor = offset + signature.len();
+        let mut chunks = 0;
+        let mut complete = false;
+        while let Ok(chunk) = ChunkRef::try_from(&data[cursor..]) {
+            cursor += 12 + chunk.length() as usize;
+            chunks += 1;
+            if chunk.chunk_type().to_string() == "IEND" {
+                complete = true;
+                break;
+            }
+        }
+
+        if complete || (partial && chunks > 0) {
+            carved.push(Carved { offset, bytes: &data[offset..cursor], chunks, complete });
+        }
+    }
+    carved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    #[test]
+    fn test_carve() {
+        let cut = DICE.len() - 100;
+        let blob = [&[0x89u8; 13][..], DICE, &Png::STANDARD_HEADER, b"garbage", &DICE[..cut], &[0; 20]].concat();
+        let second = 13 + DICE.len() + 8 + 7;
+
+        let carved = carve(&blob, false);
+        assert_eq!(carved.len(), 1);
+        assert_eq!(carved[0].offset, 13);
+        assert_eq!(carved[0].bytes, DICE);
+        assert!(carved[0].complete);
+
+        let carved = carve(&blob, true);
+        assert_eq!(carved.len(), 2);
+        assert_eq!(carved[1].offset, second);
+        assert!(!carved[1].complete);
+        assert!(carved[1].chunks > 1);
+        assert!(carved[1].bytes.len() < cut);
+        assert!(DICE.starts_with(carved[1].bytes));
+    }
+
+    #[test]
+    fn test_carve_nothing() {
+        assert!(carve(b"no images here", true).is_empty());
+        assert!(carve(&Png::STANDARD_HEADER, true).is_empty());
+    }
+}
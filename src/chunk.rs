@@ -1,28 +1,40 @@
 use core::fmt;
 use std::fmt::{Display, Formatter};
-use std::io::{BufReader, Read};
 use crc::Crc;
 
 use crate::chunk_type::ChunkType;
 use crate::{Result, Error, MAX_CHUNK_LEN};
 
+/// A single PNG chunk: its type and data. Length and CRC are derived on demand.
+#[derive(Clone)]
 pub struct Chunk {
     chunk_type: ChunkType,
     data: Vec<u8>
 }
 
 impl Chunk {
+    /// Creates a chunk.
+    ///
+    /// # Panics
+    /// Panics if `data` is longer than [`MAX_CHUNK_LEN`]. Use [`Chunk::try_new`] for untrusted data.
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self {
-        if data.len() > MAX_CHUNK_LEN as usize {
-            panic!("Max chunk length exceeded. CURRENT: {} MAX: {}", data.len(), MAX_CHUNK_LEN)
+        match Self::try_new(chunk_type, data) {
+            Ok(chunk) => chunk,
+            Err(e) => panic!("{e}")
         }
+    }
+
+    /// Creates a chunk, failing if `data` is longer than [`MAX_CHUNK_LEN`].
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> std::result::Result<Self, ChunkError> {
+        check_length(data.len())?;
 
-        Self {
+        Ok(Self {
             chunk_type,
             data,
-        }
+        })
     }
 
+    /// Length of the chunk data in bytes.
     pub fn length(&self) -> u32 {
         self.data.len() as u32
     }
@@ -35,19 +47,18 @@ impl Chunk {
         &self.data
     }
 
+    /// CRC-32 over the chunk type and data, as stored in the file.
     pub fn crc(&self) -> u32 {
-        let hasher = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        let mut digest = hasher.digest();
-        digest.update(&self.chunk_type.bytes());
-        digest.update(&self.data);
-        digest.finalize()
+        crc_of(&self.chunk_type, &self.data)
     }
 
+    /// Chunk data decoded as UTF-8.
     pub fn data_as_string(&self) -> Result<String> {
         let s = std::str::from_utf8(&self.data)?;
         Ok(s.to_string())
     }
 
+    /// Serialized chunk: length, type, data and CRC.
     pub fn as_bytes(&self) -> Vec<u8> {
         self.length()
             .to_be_bytes()
@@ -58,13 +69,135 @@ impl Chunk {
             .copied()
             .collect()
     }
+
+    /// Borrows this chunk as a [`ChunkRef`].
+    pub fn as_chunk_ref(&self) -> ChunkRef<'_> {
+        ChunkRef {
+            chunk_type: self.chunk_type.clone(),
+            data: &self.data,
+            stored_crc: self.crc()
+        }
+    }
+
+    /// Builds a chunk read from a file, checking its type and the stored `crc`.
+    pub(crate) fn from_parts(type_bytes: [u8; 4], data: Vec<u8>, crc: u32) -> Result<Self> {
+        let chunk_type = check_type(type_bytes)?;
+        check_crc(&chunk_type, &data, crc)?;
+        Ok(Chunk::try_new(chunk_type, data)?)
+    }
+}
+
+/// A chunk borrowed from an input buffer, with the same accessors as [`Chunk`].
+#[derive(Debug, Clone)]
+pub struct ChunkRef<'a> {
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    stored_crc: u32
+}
+
+impl<'a> ChunkRef<'a> {
+    pub fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn crc(&self) -> u32 {
+        crc_of(&self.chunk_type, self.data)
+    }
+
+    /// The CRC as stored in the file, which [`ChunkRef::scan`] does not check.
+    pub fn stored_crc(&self) -> u32 {
+        self.stored_crc
+    }
+
+    /// Checks the stored CRC against the chunk type and data.
+    pub fn verify(&self) -> Result<()> {
+        check_crc(&self.chunk_type, self.data, self.stored_crc)
+    }
+
+    /// Parses the chunk at the start of `value` without reading its data to check the CRC.
+    ///
+    /// Only the length, type and CRC fields are touched, so this is cheap on memory-mapped files.
+    pub fn scan(value: &'a [u8]) -> Result<Self> {
+        if value.len() < 12 { return Err(ChunkError::InvalidChunkLength(value.len()).into()) }
+
+        let data_length = read_u32(&value[0..4]);
+        if data_length > MAX_CHUNK_LEN { return Err(ChunkError::DataTooLong(data_length as usize).into()) }
+
+        let data_end = 8 + data_length as usize;
+        if value.len() < data_end + 4 {
+            return Err(ChunkError::Truncated { declared: data_length, available: value.len().saturating_sub(8).min(data_length as usize) }.into())
+        }
+
+        Ok(ChunkRef {
+            chunk_type: check_type([value[4], value[5], value[6], value[7]])?,
+            data: &value[8..data_end],
+            stored_crc: read_u32(&value[data_end..data_end + 4])
+        })
+    }
+
+    /// Chunk data as a UTF-8 string slice, without copying.
+    pub fn data_as_str(&self) -> Result<&'a str> {
+        Ok(std::str::from_utf8(self.data)?)
+    }
+
+    /// Copies the borrowed data into an owned [`Chunk`].
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk {
+            chunk_type: self.chunk_type.clone(),
+            data: self.data.to_vec()
+        }
+    }
+}
+
+fn check_length(length: usize) -> std::result::Result<(), ChunkError> {
+    if length > MAX_CHUNK_LEN as usize { return Err(ChunkError::DataTooLong(length)) }
+    Ok(())
+}
+
+pub(crate) fn crc_of(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let hasher = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+    let mut digest = hasher.digest();
+    digest.update(&chunk_type.bytes());
+    digest.update(data);
+    digest.finalize()
+}
+
+pub(crate) fn check_type(type_bytes: [u8; 4]) -> Result<ChunkType> {
+    let chunk_type = ChunkType::try_from(type_bytes)?;
+    if !chunk_type.is_valid() { return Err(ChunkError::InvalidChunkType(type_bytes).into()) }
+    Ok(chunk_type)
+}
+
+fn check_crc(chunk_type: &ChunkType, data: &[u8], crc: u32) -> Result<()> {
+    let calculated_crc = crc_of(chunk_type, data);
+    if crc != calculated_crc {
+        return Err(ChunkError::CrcMismatch {
+            chunk_type: chunk_type.clone(),
+            expected: crc,
+            actual: calculated_crc
+        }.into())
+    }
+
+    Ok(())
 }
 
 #[derive(Debug)]
-enum ChunkError {
+#[non_exhaustive]
+pub enum ChunkError {
     InvalidChunkLength(usize),
-    InvalidChunkType,
-    CrcMismatch(u32, u32)
+    InvalidChunkType([u8; 4]),
+    CrcMismatch { chunk_type: ChunkType, expected: u32, actual: u32 },
+    DataTooLong(usize),
+    /// The declared data length runs past the end of the input.
+    Truncated { declared: u32, available: usize }
 }
 
 impl Display for ChunkError {
@@ -73,11 +206,17 @@ impl Display for ChunkError {
             ChunkError::InvalidChunkLength(length) => {
                 write!(f, "Invalid chunk length: {length}. Must be at least 12 bytes.")
             }
-            ChunkError::InvalidChunkType => {
-                write!(f, "Invalid chunk type detected.")
+            ChunkError::InvalidChunkType(bytes) => {
+                write!(f, "Invalid chunk type detected: {bytes:?}")
+            }
+            ChunkError::CrcMismatch { chunk_type, expected, actual } => {
+                write!(f, "Invalid CRC detected in {chunk_type} chunk. Expected: {expected}, actual: {actual}")
             }
-            ChunkError::CrcMismatch(expected, actual) => {
-                write!(f, "Invalid CRC detected. Expected: {expected}, actual: {actual}")
+            ChunkError::DataTooLong(length) => {
+                write!(f, "Max chunk length exceeded. CURRENT: {length} MAX: {MAX_CHUNK_LEN}")
+            }
+            ChunkError::Truncated { declared, available } => {
+                write!(f, "Chunk truncated. Declared data length: {declared}, available: {available}")
             }
         }
     }
@@ -89,39 +228,38 @@ impl TryFrom<&[u8]> for Chunk {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
-        if value.len() < 12 { return Err(Box::new(ChunkError::InvalidChunkLength(value.len()))) }
-        let mut reader = BufReader::new(value);
-        let mut buffer: [u8; 4] = [0, 0, 0, 0];
-
-        reader.read_exact(&mut buffer)?;
-        let data_length = u32::from_be_bytes(buffer);
-
-        reader.read_exact(&mut buffer)?;
-        let chunk_type = ChunkType::try_from(buffer)?;
-        if !chunk_type.is_valid() { return Err(Box::new(ChunkError::InvalidChunkType)) }
+        ChunkRef::try_from(value).map(|chunk| chunk.to_chunk())
+    }
+}
 
-        let mut data = vec![0; data_length as usize];
-        reader.read_exact(&mut data)?;
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = Error;
 
-        let chunk = Chunk::new(chunk_type, data);
+    fn try_from(value: &'a [u8]) -> std::result::Result<Self, Self::Error> {
+        let chunk = ChunkRef::scan(value)?;
+        chunk.verify()?;
+        Ok(chunk)
+    }
+}
 
-        reader.read_exact(&mut buffer)?;
-        let crc = u32::from_be_bytes(buffer);
-        let calculated_crc = chunk.crc();
 
-        if crc != calculated_crc { return Err(Box::new(ChunkError::CrcMismatch(crc, calculated_crc))) }
+pub(crate) fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
 
-        Ok(chunk)
+impl Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_chunk_ref().fmt(f)
     }
 }
 
-impl Display for Chunk {
+impl Display for ChunkRef<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Chunk {{",)?;
         writeln!(f, "  Length: {}", self.length())?;
         writeln!(f, "  Type: {}", self.chunk_type())?;
         writeln!(f, "  Data: {} bytes", self.data().len())?;
-        writeln!(f, "  Crc: {}", self.crc())?;
+        writeln!(f, "  Crc: {}", self.stored_crc())?;
         writeln!(f, "}}",)?;
         Ok(())
     }
@@ -224,6 +362,43 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_try_new_too_long() {
+        // Checked without materializing a 2 GiB buffer
+        assert!(check_length(MAX_CHUNK_LEN as usize).is_ok());
+        assert!(matches!(check_length(MAX_CHUNK_LEN as usize + 1), Err(ChunkError::DataTooLong(_))));
+
+        let chunk_data = get_chunk_data(MAX_CHUNK_LEN + 1, "RuSt".as_bytes(), &[], 0);
+        assert!(matches!(ChunkRef::scan(&chunk_data), Err(Error::Chunk { source: ChunkError::DataTooLong(_), .. })));
+    }
+
+    #[test]
+    fn test_declared_length_too_long() {
+        let chunk_data = get_chunk_data(u32::MAX, "RuSt".as_bytes(), &[], 0);
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert!(matches!(chunk, Err(Error::Chunk { source: ChunkError::DataTooLong(_), .. })));
+    }
+
+    #[test]
+    fn test_declared_length_past_input() {
+        let chunk_data = get_chunk_data(MAX_CHUNK_LEN, "RuSt".as_bytes(), &[], 0);
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert!(matches!(chunk, Err(Error::Chunk { source: ChunkError::Truncated { available: 4, .. }, .. })));
+    }
+
+    #[test]
+    fn test_crc_mismatch_error() {
+        let chunk_data = get_chunk_data(2, "RuSt".as_bytes(), b"hi", 1);
+
+        match Chunk::try_from(chunk_data.as_ref()) {
+            Err(Error::Chunk { offset: 0, source: ChunkError::CrcMismatch { chunk_type, expected, .. } }) => {
+                assert_eq!(chunk_type.to_string(), "RuSt");
+                assert_eq!(expected, 1);
+            }
+            _ => panic!("expected CRC mismatch")
+        }
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -3,16 +3,19 @@ use std::str::FromStr;
 use std::str;
 use crate::{Result, Error};
 
+/// The four-byte type code of a PNG chunk, e.g. `IHDR` or `ruSt`.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ChunkType {
     bytes: [u8; 4]
 }
 
 impl ChunkType {
+    /// Raw type code bytes.
     pub fn bytes(&self) -> [u8; 4] {
         self.bytes
     }
 
+    /// Whether every byte is an ASCII letter and the reserved bit is unset.
     pub fn is_valid(&self) -> bool {
         for byte in &self.bytes {
             match byte {
@@ -23,18 +26,22 @@ impl ChunkType {
         self.is_reserved_bit_valid()
     }
 
+    /// Critical chunks (uppercase first letter) are required to display the image.
     pub fn is_critical(&self) -> bool {
         (b'A'..=b'Z').contains(&self.bytes[0])
     }
 
+    /// Public chunks (uppercase second letter) are defined by the PNG specification.
     pub fn is_public(&self) -> bool {
         (b'A'..=b'Z').contains(&self.bytes[1])
     }
 
+    /// The third letter must be uppercase in the current version of the specification.
     pub fn is_reserved_bit_valid(&self) -> bool {
         (b'A'..=b'Z').contains(&self.bytes[2])
     }
 
+    /// Safe-to-copy chunks (lowercase fourth letter) may be kept by editors that modify the image.
     pub fn is_safe_to_copy(&self) -> bool {
         (b'a'..=b'z').contains(&self.bytes[3])
     }
@@ -51,7 +58,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
 
     fn try_from(value: [u8; 4]) -> Result<Self> {
         if value.len() != 4 {
-            return Err(Box::new(ChunkTypeError::InvalidLength(value.len())));
+            return Err(ChunkTypeError::InvalidLength(value.len()).into());
         }
 
         Ok(Self { bytes: value })
@@ -65,7 +72,7 @@ impl FromStr for ChunkType {
         let bytes = s.as_bytes();
 
         if bytes.len() != 4 {
-            return Err(Box::new(ChunkTypeError::InvalidLength(bytes.len())));
+            return Err(ChunkTypeError::InvalidLength(bytes.len()).into());
         }
 
         let valid_chars = bytes
@@ -73,7 +80,7 @@ impl FromStr for ChunkType {
             .all(|&b| (b'A'..=b'Z').contains(&b) || (b'a'..=b'z').contains(&b));
 
         if !valid_chars {
-            return Err(Box::new(ChunkTypeError::InvalidCharacter));
+            return Err(ChunkTypeError::InvalidCharacter.into());
         }
 
         let sized: [u8; 4] = [bytes[0], bytes[1], bytes[2], bytes[3]];
@@ -82,7 +89,8 @@ impl FromStr for ChunkType {
 }
 
 #[derive(Debug)]
-enum ChunkTypeError {
+#[non_exhaustive]
+pub enum ChunkTypeError {
     InvalidLength(usize),
     InvalidCharacter
 }
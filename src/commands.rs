@@ -1,14 +1,43 @@
-use std::fmt::{Display, Formatter};
-use std::fs;
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::env;
+use std::error::Error as _;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use crate::{Result, Error};
-use crate::args::{Cli, Commands, DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs};
+use pngcoder::Result;
+use crate::args::{AnalyzeArgs, CarveArgs, CheckArgs, Cli, Commands, DecodeArgs, EncodeArgs, KeygenArgs, Method, PrintArgs, RemoveArgs, RepairArgs, SignArgs, TextCommands, TextGetArgs, TextListArgs, TextRemoveArgs, TextSetArgs, TrailingAppendArgs, TrailingCommands, TrailingExtractArgs, TrailingStripArgs, VerifyArgs};
 
 use clap::Parser;
-use crate::chunk::Chunk;
-use crate::chunk_type::ChunkType;
-use crate::png::Png;
+use pngcoder::analyze;
+use pngcoder::carve;
+use pngcoder::chunk::Chunk;
+use pngcoder::chunk_type::ChunkType;
+use pngcoder::compress;
+use pngcoder::crypto::{self, Identity, PublicKey};
+use pngcoder::file::PngFile;
+use pngcoder::fragment;
+use pngcoder::parse::{ParseMode, ParseOptions};
+use pngcoder::png::{Occurrence, Png, PngError};
+use pngcoder::repair;
+use pngcoder::signature::{self, SignatureError, SigningKey, VerifyingKey};
+use pngcoder::stego;
+use pngcoder::stream::{self, ChunkReader, ChunkWriter};
+use pngcoder::text::{self, TextChunk};
+
+const PASSPHRASE_VAR: &str = "PNGC_PASSPHRASE";
+const SCATTER_PASSPHRASE_VAR: &str = "PNGC_SCATTER_PASSPHRASE";
+
+type FileReader = ChunkReader<BufReader<File>>;
+type FileWriter = ChunkWriter<BufWriter<File>>;
+
+/// How `decode` opens encrypted payloads.
+enum PayloadKey {
+    None,
+    Passphrase(String),
+    Identity(Identity)
+}
 
 pub struct Handler{}
 
@@ -19,74 +48,578 @@ impl Handler {
             Commands::Encode(arg) => Self::handle_encode(arg),
             Commands::Decode(arg) => Self::handle_decode(arg),
             Commands::Remove(arg) => Self::handle_remove(arg),
-            Commands::Print(arg) => Self::handle_print(arg)
+            Commands::Print(arg) => Self::handle_print(arg),
+            Commands::Check(arg) => Self::handle_check(arg),
+            Commands::Keygen(arg) => Self::handle_keygen(arg),
+            Commands::Sign(arg) => Self::handle_sign(arg),
+            Commands::Verify(arg) => Self::handle_verify(arg),
+            Commands::Analyze(arg) => Self::handle_analyze(arg),
+            Commands::Trailing(TrailingCommands::Extract(arg)) => Self::handle_trailing_extract(arg),
+            Commands::Trailing(TrailingCommands::Strip(arg)) => Self::handle_trailing_strip(arg),
+            Commands::Trailing(TrailingCommands::Append(arg)) => Self::handle_trailing_append(arg),
+            Commands::Repair(arg) => Self::handle_repair(arg),
+            Commands::Carve(arg) => Self::handle_carve(arg),
+            Commands::Text(TextCommands::List(arg)) => Self::handle_text_list(arg),
+            Commands::Text(TextCommands::Get(arg)) => Self::handle_text_get(arg),
+            Commands::Text(TextCommands::Set(arg)) => Self::handle_text_set(arg),
+            Commands::Text(TextCommands::Remove(arg)) => Self::handle_text_remove(arg)
         }
     }
 
     fn handle_encode(args: &EncodeArgs) -> Result<()> {
-        let mut png = Png::from_file(&args.file_path)?;
         let chunk_type = ChunkType::from_str(&args.chunk_type)?;
-        let data = args.message.bytes().collect();
+        let data = match &args.input {
+            Some(input) => Self::read_input(input)?,
+            None => args.message.clone().unwrap_or_default().into_bytes()
+        };
 
-        let chunk = Chunk::new(chunk_type, data);
+        let data = match args.compress {
+            Some(method) => compress::compress(&data, method)?,
+            None => data
+        };
 
-        png.append_chunk(chunk);
+        let data = if args.encrypt {
+            crypto::encrypt(&data, Self::read_passphrase(true)?.as_bytes())?
+        } else if !args.recipients.is_empty() {
+            let mut recipients = Vec::new();
+            for path in &args.recipients {
+                recipients.extend(Self::read_keys::<PublicKey>(path)?);
+            }
+            crypto::encrypt_to(&data, &recipients)?
+        } else {
+            data
+        };
+
+        let output = args.output_file.as_ref().unwrap_or(&args.file_path);
 
-        let output = match &args.output_file {
-            Some(output) => output,
-            None => &args.file_path
+        if args.method == Method::Lsb {
+            if args.split.is_some() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "--split cannot be used with --method lsb").into());
+            }
+            let options = Self::stego_options(args.scatter, true)?.with_strength(args.strength)?;
+            Self::edit(&args.file_path, output, |png| {
+                let mut pixels = png.pixels()?;
+                println!("Capacity: {} bytes, payload: {} bytes", stego::capacity(pixels.header(), &options)?, data.len());
+                let changed = stego::embed(&mut pixels, &chunk_type, &data, &options)?;
+                println!("Changed {changed} samples");
+                png.set_pixels(&pixels)
+            })?;
+            println!("Encoding successful!");
+            return Ok(());
+        }
+
+        if args.scatter || args.strength != 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--scatter and --strength require --method lsb").into());
+        }
+
+        let chunks = match args.split {
+            Some(chunk_len) => fragment::split(&data, chunk_len - fragment::HEADER_LEN)?
+                .into_iter()
+                .map(|fragment| Chunk::try_new(chunk_type.clone(), fragment))
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+            None => vec![Chunk::try_new(chunk_type, data)?]
         };
 
-        fs::write(output, png.as_bytes())?;
+        Self::rewrite(&args.file_path, output, |reader, writer| {
+            stream::insert_chunks(reader, writer, chunks, args.position)
+        })?;
         println!("Encoding successful!");
         Ok(())
     }
 
     fn handle_decode(args: &DecodeArgs) -> Result<()> {
-        let png = Png::from_file(&args.file_path)?;
-        let maybe_chunk = Png::chunk_by_type(&png, &args.chunk_type);
-        match maybe_chunk {
-            Some(chunk) => {
-                let chunk_data = chunk.data_as_string()?;
-                println!("{chunk_data}");
-                Ok(())
+        if args.method == Method::Lsb {
+            let png = Png::from_file(&args.file_path)?;
+            let options = Self::stego_options(args.scatter, false)?;
+            let data = stego::extract(&png.pixels()?, &ChunkType::from_str(&args.chunk_type)?, &options)?;
+            return Self::write_payload(args, &Self::open_payload(Cow::Owned(data), &Self::payload_key(args)?)?);
+        }
+
+        if args.scatter {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--scatter requires --method lsb").into());
+        }
+
+        let file = PngFile::open(&args.file_path)?;
+        let png = file.png()?;
+        let chunks: Vec<_> = png.chunks_by_type(&args.chunk_type).collect();
+        let (_, first) = chunks.first().ok_or_else(|| PngError::ChunkNotFound(args.chunk_type.clone()))?;
+        let key = Self::payload_key(args)?;
+
+        if args.all || args.index.is_some() {
+            let occurrence = Self::occurrence(args.all, args.index);
+            let selected: Vec<_> = chunks.iter().enumerate().filter(|(n, _)| occurrence.matches(*n)).collect();
+            if selected.is_empty() {
+                return Err(PngError::OccurrenceNotFound { chunk_type: args.chunk_type.clone(), index: args.index.unwrap_or(0), count: chunks.len() }.into());
             }
-            None => {
-                Err(Box::new(HandlerError::ChunkNotFound))
+
+            for (n, (index, chunk)) in selected {
+                chunk.verify()?;
+                let data = Self::open_payload(Cow::Borrowed(chunk.data()), &key)?;
+                if args.all {
+                    println!("{} #{n} at chunk {index}, byte {}:", args.chunk_type, png.offset_of(*index));
+                    match std::str::from_utf8(&data) {
+                        Ok(text) => println!("{text}"),
+                        Err(_) => println!("<{} bytes of binary data, use --index {n} --output to save>", data.len())
+                    }
+                } else {
+                    Self::write_payload(args, &data)?;
+                }
             }
+            return Ok(());
         }
+
+        let data = if args.split || args.message_id.is_some() {
+            let fragments: Vec<_> = chunks.iter().map(|(_, c)| c).filter(|c| fragment::is_fragment(c.data())).collect();
+            fragments.iter().try_for_each(|c| c.verify())?;
+            Cow::Owned(fragment::reassemble(fragments.iter().map(|c| c.data()), args.message_id)?)
+        } else {
+            if fragment::Fragment::parse(first.data()).is_ok() {
+                let message = format!("The {} chunk holds part of a split payload. Use --split to reassemble it, or --index 0 to decode the chunk as is", args.chunk_type);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message).into());
+            }
+            if chunks.len() > 1 {
+                eprintln!("Found {} {} chunks, decoding the first. Use --all or --index to choose", chunks.len(), args.chunk_type);
+            }
+            first.verify()?;
+            Cow::Borrowed(first.data())
+        };
+        Self::write_payload(args, &Self::open_payload(data, &key)?)
     }
 
     fn handle_remove(args: &RemoveArgs) -> Result<()> {
-        let mut png = Png::from_file(&args.file_path)?;
-        png.remove_chunk(&args.chunk_type)?;
-
-        fs::write(&args.file_path, png.as_bytes())?;
+        let removed = Self::rewrite(&args.file_path, &args.file_path, |reader, writer| {
+            stream::remove_chunks(reader, writer, &args.chunk_type, Self::occurrence(args.all, args.index))
+        })?;
 
-        println!("Chunk removed!");
+        match removed.len() {
+            1 => println!("Chunk removed!"),
+            count => println!("{count} chunks removed!")
+        }
         Ok(())
     }
 
     fn handle_print(args: &PrintArgs) -> Result<()> {
-        let png = Png::from_file(&args.file_path)?;
+        if args.parse != ParseMode::Strict {
+            let parsed = Png::parse_with(&fs::read(&args.file_path)?, &ParseOptions { mode: args.parse })?;
+            match parsed.png.image_header() {
+                Ok(header) => println!("{}", header),
+                Err(e) => println!("ImageHeader: {}\n", e.source().unwrap_or(&e))
+            }
+            println!("{}", parsed.png);
+            for diagnostic in &parsed.diagnostics {
+                println!("{diagnostic}");
+            }
+            return Ok(());
+        }
+
+        let file = PngFile::open(&args.file_path)?;
+        let png = file.png()?;
+        match png.image_header() {
+            Ok(header) => println!("{}", header),
+            Err(e) => println!("ImageHeader: {}\n", e.source().unwrap_or(&e))
+        }
         println!("{}", png);
         Ok(())
     }
-}
 
-#[derive(Debug)]
-enum HandlerError {
-    ChunkNotFound
-}
+    fn handle_check(args: &CheckArgs) -> Result<()> {
+        let (png, diagnostics) = match args.parse {
+            ParseMode::Strict => (Png::from_file(&args.file_path)?, Vec::new()),
+            mode => {
+                let parsed = Png::parse_with(&fs::read(&args.file_path)?, &ParseOptions { mode })?;
+                (parsed.png, parsed.diagnostics)
+            }
+        };
+        let violations = png.validate();
+        if violations.is_empty() && diagnostics.is_empty() {
+            println!("No problems found!");
+            return Ok(());
+        }
 
-impl Display for HandlerError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            HandlerError::ChunkNotFound => {
-                write!(f, "Chunk was not found!")
+        for diagnostic in &diagnostics {
+            println!("{diagnostic}");
+        }
+        for violation in &violations {
+            println!("{violation}");
+        }
+        Err(PngError::ValidationFailed(violations.len() + diagnostics.len()).into())
+    }
+
+    fn handle_analyze(args: &AnalyzeArgs) -> Result<()> {
+        let file = PngFile::open(&args.file_path)?;
+        let report = analyze::analyze(&file)?;
+        println!("Score: {}/100 ({})", report.score(), report.verdict());
+
+        if report.findings.is_empty() {
+            println!("\nNo suspicious regions found");
+        } else {
+            println!("\nFindings:");
+            for finding in &report.findings {
+                println!("  {finding}");
             }
         }
+
+        println!("\nChunks:");
+        println!("  {:>5}  {:<4}  {:>10}  {:>10}  {:>7}", "index", "type", "offset", "length", "entropy");
+        for chunk in &report.chunks {
+            let crc = if chunk.crc_ok { "" } else { "  bad CRC" };
+            println!("  {:>5}  {:<4}  {:>10}  {:>10}  {:>7.3}{crc}", chunk.index, chunk.chunk_type, chunk.offset, chunk.length, chunk.entropy);
+        }
+
+        match &report.lsb {
+            Some(lsb) => {
+                let rates: Vec<String> = lsb.rs_rates.iter().map(|r| format!("{:.1}%", r * 100.0)).collect();
+                println!("\nLSB analysis of {} colour samples:", lsb.samples);
+                if lsb.rows < lsb.height {
+                    println!("  Sampled from the first {} of {} rows, as the image has over {} pixels", lsb.rows, lsb.height, analyze::MAX_ANALYZED_PIXELS);
+                }
+                println!("  Chi-square embedding probability: {:.3}", lsb.chi_square);
+                println!("  RS estimated embedding rate: {:.1}% (per channel: {})", lsb.rs_rate() * 100.0, rates.join(", "));
+            }
+            None => println!("\nLSB analysis skipped: the pixels could not be decoded, are indexed or below 8 bits, or are too many to sample")
+        }
+        Ok(())
+    }
+
+    fn handle_trailing_extract(args: &TrailingExtractArgs) -> Result<()> {
+        let file = PngFile::open(&args.file_path)?;
+        let png = file.png()?;
+        let trailing = png.trailing_data();
+        if trailing.is_empty() { return Err(PngError::NoTrailingData.into()) }
+
+        Self::write_output(&args.output, trailing)?;
+        if args.output != Path::new("-") {
+            println!("Extracted {} bytes from byte {}", trailing.len(), png.trailing_offset());
+        }
+        Ok(())
+    }
+
+    fn handle_trailing_strip(args: &TrailingStripArgs) -> Result<()> {
+        let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+        let removed = Self::rewrite(&args.file_path, output, |mut reader, writer| {
+            reader.by_ref().try_for_each(|chunk| writer.write_chunk(&chunk?))?;
+            Ok(io::copy(&mut reader.into_inner(), &mut io::sink())?)
+        })?;
+        println!("Removed {removed} bytes after IEND");
+        Ok(())
+    }
+
+    fn handle_trailing_append(args: &TrailingAppendArgs) -> Result<()> {
+        let data = Self::read_input(&args.input)?;
+        let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+        Self::rewrite(&args.file_path, output, |mut reader, writer| {
+            reader.by_ref().try_for_each(|chunk| writer.write_chunk(&chunk?))?;
+            writer.write_trailing_data(reader.into_inner())?;
+            writer.write_trailing_data(data.as_slice())
+        })?;
+        println!("Appended {} bytes after IEND", data.len());
+        Ok(())
+    }
+
+    fn handle_repair(args: &RepairArgs) -> Result<()> {
+        let repaired = repair::repair(&fs::read(&args.file_path)?)?;
+        if repaired.changes.is_empty() {
+            println!("Nothing to repair");
+            return Ok(());
+        }
+
+        for change in &repaired.changes {
+            println!("{change}");
+        }
+        let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+        let temp_path = Self::temp_path(output);
+        fs::write(&temp_path, repaired.png.as_bytes_with_trailing_data())?;
+        fs::rename(&temp_path, output)?;
+        Ok(())
+    }
+
+    fn handle_carve(args: &CarveArgs) -> Result<()> {
+        let file = PngFile::open(&args.input)?;
+        let carved = carve::carve(&file, args.partial);
+        if carved.is_empty() {
+            println!("No PNGs found");
+            return Ok(());
+        }
+
+        fs::create_dir_all(&args.output_dir)?;
+        for png in &carved {
+            let suffix = if png.complete { "" } else { "-partial" };
+            let path = args.output_dir.join(format!("carved-{}{suffix}.png", png.offset));
+            fs::write(&path, png.bytes)?;
+            println!("Byte {}: {} chunks, {} bytes -> {}", png.offset, png.chunks, png.bytes.len(), path.display());
+        }
+        println!("Carved {} PNG(s)", carved.len());
+        Ok(())
+    }
+
+    fn handle_keygen(args: &KeygenArgs) -> Result<()> {
+        let (identity, public_key) = if args.signing {
+            let key = SigningKey::generate();
+            (key.to_string(), key.verifying_key().to_string())
+        } else {
+            let identity = Identity::generate();
+            (identity.to_string(), identity.public_key().to_string())
+        };
+
+        let mut options = File::options();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&args.identity_file)?;
+        writeln!(file, "# public key: {public_key}")?;
+        writeln!(file, "{identity}")?;
+
+        let mut public_path = args.identity_file.clone().into_os_string();
+        public_path.push(".pub");
+        fs::write(&public_path, format!("{public_key}\n"))?;
+
+        println!("Public key: {public_key}");
+        Ok(())
     }
-}
 
-impl std::error::Error for HandlerError {}
\ No newline at end of file
+    fn handle_sign(args: &SignArgs) -> Result<()> {
+        let key = Self::read_keys::<SigningKey>(&args.key_file)?
+            .into_iter()
+            .next()
+            .ok_or(crypto::CryptoError::InvalidKey("PNGC-SIGNING-KEY".to_string()))?;
+        let chunk_types = args.chunk_types
+            .iter()
+            .map(|t| ChunkType::from_str(t))
+            .collect::<Result<Vec<_>>>()?;
+
+        let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+        Self::edit(&args.file_path, output, |png| signature::sign(png, &key, &chunk_types))?;
+
+        println!("Signed by {}", key.verifying_key());
+        Ok(())
+    }
+
+    fn handle_verify(args: &VerifyArgs) -> Result<()> {
+        let png = Png::from_file(&args.file_path)?;
+        let verification = signature::verify(&png)?;
+
+        let covered: Vec<String> = verification.chunk_types.iter().map(ToString::to_string).collect();
+        println!("Signed by {}", verification.signer);
+        println!("Covers: {}", covered.join(", "));
+        for change in &verification.changes {
+            println!("{change}");
+        }
+        for (index, chunk_type) in &verification.unsigned {
+            println!("Not signed: {chunk_type} (chunk {index})");
+        }
+
+        if let Some(path) = &args.key {
+            let trusted = Self::read_keys::<VerifyingKey>(path)?;
+            if !trusted.contains(&verification.signer) {
+                return Err(SignatureError::UntrustedSigner(verification.signer.to_string()).into());
+            }
+        }
+        if !verification.is_intact() {
+            return Err(SignatureError::Modified(verification.changes.len()).into());
+        }
+
+        println!("Signature is valid!");
+        Ok(())
+    }
+
+    fn handle_text_list(args: &TextListArgs) -> Result<()> {
+        let png = Png::from_file(&args.file_path)?;
+        for (index, text) in text::text_chunks(&png) {
+            match text {
+                Ok(text) => println!("{text}"),
+                Err(e) => println!("Chunk {index}: {}", e.source().unwrap_or(&e))
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_text_get(args: &TextGetArgs) -> Result<()> {
+        let png = Png::from_file(&args.file_path)?;
+        println!("{}", text::get_text(&png, &args.keyword)?.text_value());
+        Ok(())
+    }
+
+    fn handle_text_set(args: &TextSetArgs) -> Result<()> {
+        let international = args.international
+            || !args.language.is_empty()
+            || !args.translated_keyword.is_empty()
+            || args.text.chars().any(|c| c > '\u{FF}');
+
+        let text = if international {
+            TextChunk::international(&args.keyword, &args.language, &args.translated_keyword, &args.text, args.compress)?
+        } else if args.compress {
+            TextChunk::compressed(&args.keyword, &args.text)?
+        } else {
+            TextChunk::text(&args.keyword, &args.text)?
+        };
+
+        let output = args.output_file.as_ref().unwrap_or(&args.file_path);
+        Self::edit(&args.file_path, output, |png| text::set_text(png, text))?;
+        println!("Text set!");
+        Ok(())
+    }
+
+    fn handle_text_remove(args: &TextRemoveArgs) -> Result<()> {
+        Self::edit(&args.file_path, &args.file_path, |png| text::remove_text(png, &args.keyword))?;
+        println!("Text removed!");
+        Ok(())
+    }
+
+    /// Reads `input` into memory, applies `edit` and writes the result to `output` with [`Self::rewrite`].
+    /// Trailing data after IEND is copied as is.
+    fn edit<T, F>(input: &Path, output: &Path, edit: F) -> Result<T>
+    where
+        F: FnOnce(&mut Png) -> Result<T>
+    {
+        Self::rewrite(input, output, |mut reader, writer| {
+            let mut png = Png::from_chunks(reader.by_ref().collect::<Result<_>>()?);
+            let value = edit(&mut png)?;
+            png.chunks().iter().try_for_each(|chunk| writer.write_chunk(chunk))?;
+            writer.write_trailing_data(reader.into_inner())?;
+            Ok(value)
+        })
+    }
+
+    /// Streams `input` through `transform` into `output`.
+    ///
+    /// Chunks are written to a temporary file next to `output` that replaces it only on success,
+    /// so `input` and `output` may be the same file.
+    fn rewrite<T, F>(input: &Path, output: &Path, transform: F) -> Result<T>
+    where
+        F: FnOnce(FileReader, &mut FileWriter) -> Result<T>
+    {
+        let reader = ChunkReader::new(BufReader::new(File::open(input)?))?;
+        let temp_path = Self::temp_path(output);
+
+        let result = File::create(&temp_path)
+            .map_err(Into::into)
+            .and_then(|file| ChunkWriter::new(BufWriter::new(file)))
+            .and_then(|mut writer| {
+                let value = transform(reader, &mut writer)?;
+                writer.into_inner()?;
+                Ok(value)
+            });
+
+        match result {
+            Ok(value) => {
+                fs::rename(&temp_path, output)?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(e)
+            }
+        }
+    }
+
+    /// Reads the passphrase from `PNGC_PASSPHRASE`, or prompts for it on the terminal.
+    fn read_passphrase(confirm: bool) -> Result<String> {
+        Self::prompt_passphrase(PASSPHRASE_VAR, "Passphrase", confirm)
+    }
+
+    fn prompt_passphrase(var: &str, prompt: &str, confirm: bool) -> Result<String> {
+        if let Ok(passphrase) = env::var(var) {
+            return Ok(passphrase);
+        }
+
+        let passphrase = rpassword::prompt_password(format!("{prompt}: "))?;
+        if confirm && rpassword::prompt_password(format!("Confirm {}: ", prompt.to_lowercase()))? != passphrase {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Passphrases do not match").into());
+        }
+        Ok(passphrase)
+    }
+
+    /// Embedding order for `--method lsb`, keyed by a passphrase of its own if `scatter`.
+    fn stego_options(scatter: bool, confirm: bool) -> Result<stego::Options> {
+        if !scatter { return Ok(stego::Options::default()) }
+        stego::Options::keyed(Self::prompt_passphrase(SCATTER_PASSPHRASE_VAR, "Scatter passphrase", confirm)?.as_bytes())
+    }
+
+    fn occurrence(all: bool, index: Option<usize>) -> Occurrence {
+        match (all, index) {
+            (true, _) => Occurrence::All,
+            (false, Some(index)) => Occurrence::Nth(index),
+            (false, None) => Occurrence::First
+        }
+    }
+
+    /// Reads the passphrase or identity needed to decrypt payloads, once for every chunk decoded.
+    fn payload_key(args: &DecodeArgs) -> Result<PayloadKey> {
+        if args.decrypt {
+            return Ok(PayloadKey::Passphrase(Self::read_passphrase(false)?));
+        }
+        match &args.identity {
+            Some(path) => Self::read_keys::<Identity>(path)?
+                .into_iter()
+                .next()
+                .map(PayloadKey::Identity)
+                .ok_or_else(|| crypto::CryptoError::InvalidKey("PNGC-SECRET-KEY".to_string()).into()),
+            None => Ok(PayloadKey::None)
+        }
+    }
+
+    /// Decrypts and decompresses a payload as needed.
+    fn open_payload<'a>(data: Cow<'a, [u8]>, key: &PayloadKey) -> Result<Cow<'a, [u8]>> {
+        let data = match key {
+            PayloadKey::Passphrase(passphrase) => Cow::Owned(crypto::decrypt(&data, passphrase.as_bytes())?),
+            PayloadKey::Identity(identity) => Cow::Owned(crypto::decrypt_with(&data, identity)?),
+            PayloadKey::None => data
+        };
+
+        if compress::is_compressed(&data) {
+            Ok(Cow::Owned(compress::decompress(&data)?))
+        } else {
+            Ok(data)
+        }
+    }
+
+    fn write_payload(args: &DecodeArgs, data: &[u8]) -> Result<()> {
+        match &args.output {
+            Some(output) => Self::write_output(output, data),
+            None if args.raw => Self::write_output(Path::new("-"), data),
+            None => {
+                let chunk_data = std::str::from_utf8(data)?;
+                println!("{chunk_data}");
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads one key per line from a key file, skipping blank lines and `#` comments.
+    fn read_keys<K: FromStr<Err = pngcoder::Error>>(path: &Path) -> Result<Vec<K>> {
+        fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(K::from_str)
+            .collect()
+    }
+
+    fn read_input(path: &Path) -> Result<Vec<u8>> {
+        if path == Path::new("-") {
+            let mut data = Vec::new();
+            io::stdin().lock().read_to_end(&mut data)?;
+            Ok(data)
+        } else {
+            Ok(fs::read(path)?)
+        }
+    }
+
+    fn write_output(path: &Path, data: &[u8]) -> Result<()> {
+        if path == Path::new("-") {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(data)?;
+            stdout.flush()?;
+        } else {
+            fs::write(path, data)?;
+        }
+        Ok(())
+    }
+
+    fn temp_path(output: &Path) -> PathBuf {
+        let mut file_name = OsString::from(".");
+        file_name.push(output.file_name().unwrap_or_default());
+        file_name.push(".pngc-tmp");
+        output.with_file_name(file_name)
+    }
+}
\ No newline at end of file
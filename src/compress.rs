@@ -0,0 +1,239 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::{Error, Result};
+
+/// Marks chunk data written by [`compress`].
+pub const MAGIC: [u8; 4] = *b"PNGZ";
+
+/// Largest payload [`compress`] accepts and [`decompress`] inflates, whatever length a header declares.
+pub const MAX_DECOMPRESSED_LEN: u64 = 256 * 1024 * 1024;
+
+/// magic, method and uncompressed length.
+const HEADER_LEN: usize = 4 + 1 + 8;
+
+/// How a payload is compressed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[non_exhaustive]
+pub enum Compression {
+    /// zlib-wrapped deflate, the format PNG uses for IDAT, zTXt and iTXt.
+    #[default]
+    Deflate,
+    /// Zstandard. Requires the `zstd` feature.
+    Zstd
+}
+
+impl Compression {
+    pub fn id(&self) -> u8 {
+        match self {
+            Compression::Deflate => 1,
+            Compression::Zstd => 2
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(Compression::Deflate),
+            2 => Ok(Compression::Zstd),
+            _ => Err(CompressError::UnsupportedMethod(id.to_string()).into())
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "deflate" => Ok(Compression::Deflate),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(CompressError::UnsupportedMethod(s.to_string()).into())
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::Deflate => write!(f, "deflate"),
+            Compression::Zstd => write!(f, "zstd")
+        }
+    }
+}
+
+/// Whether `data` starts with the header written by [`compress`].
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Compresses `data` behind a header recording the method and the uncompressed length.
+pub fn compress(data: &[u8], method: Compression) -> Result<Vec<u8>> {
+    if data.len() as u64 > MAX_DECOMPRESSED_LEN { return Err(CompressError::TooLarge(data.len() as u64).into()) }
+    let mut compressed = Vec::with_capacity(HEADER_LEN + data.len() / 2);
+    compressed.extend(MAGIC);
+    compressed.push(method.id());
+    compressed.extend((data.len() as u64).to_be_bytes());
+
+    match method {
+        Compression::Deflate => compressed.extend(deflate(data)?),
+        Compression::Zstd => compressed.extend(zstd_compress(data)?)
+    }
+    Ok(compressed)
+}
+
+/// Decompresses data written by [`compress`].
+///
+/// A declared length over [`MAX_DECOMPRESSED_LEN`] is rejected as corrupted, and reading stops
+/// once the declared length is exceeded, so no input inflates to more than that limit.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    if !is_compressed(data) { return Err(CompressError::NotCompressed.into()) }
+    if data.len() < HEADER_LEN { return Err(CompressError::Corrupted.into()) }
+
+    let method = Compression::from_id(data[4])?;
+    let declared = u64::from_be_bytes(data[5..HEADER_LEN].try_into().unwrap());
+    if declared > MAX_DECOMPRESSED_LEN { return Err(CompressError::Corrupted.into()) }
+    let body = &data[HEADER_LEN..];
+
+    let decompressed = match method {
+        Compression::Deflate => inflate(body, declared)?,
+        Compression::Zstd => zstd_decompress(body, declared)?
+    };
+    if decompressed.len() as u64 != declared {
+        return Err(CompressError::LengthMismatch { declared, actual: decompressed.len() as u64 }.into());
+    }
+    Ok(decompressed)
+}
+
+/// Compresses `data` into a zlib stream, as stored in zTXt, iTXt and IDAT chunks.
+pub fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+/// Decompresses a zlib stream, reading at most `limit` bytes plus one to detect overruns.
+pub fn inflate(data: &[u8], limit: u64) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(data)
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut decompressed)
+        .map_err(|_| CompressError::Corrupted)?;
+    Ok(decompressed)
+}
+
+/// Length of a zlib stream once decompressed, counting at most `limit` bytes plus one without keeping them.
+pub(crate) fn inflated_len(data: &[u8], limit: u64) -> Result<u64> {
+    io::copy(&mut ZlibDecoder::new(data).take(limit.saturating_add(1)), &mut io::sink())
+        .map_err(|_| CompressError::Corrupted.into())
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_compress(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL)?)
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decompress(data: &[u8], limit: u64) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    zstd::Decoder::new(data)?
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut decompressed)
+        .map_err(|_| CompressError::Corrupted)?;
+    Ok(decompressed)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_compress(_data: &[u8]) -> Result<Vec<u8>> {
+    Err(CompressError::UnsupportedMethod(Compression::Zstd.to_string()).into())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decompress(_data: &[u8], _limit: u64) -> Result<Vec<u8>> {
+    Err(CompressError::UnsupportedMethod(Compression::Zstd.to_string()).into())
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CompressError {
+    NotCompressed,
+    /// The method is unknown, or was not compiled in.
+    UnsupportedMethod(String),
+    Corrupted,
+    LengthMismatch { declared: u64, actual: u64 },
+    /// The payload is longer than [`MAX_DECOMPRESSED_LEN`].
+    TooLarge(u64)
+}
+
+impl Display for CompressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressError::NotCompressed => {
+                write!(f, "Data is not compressed")
+            }
+            CompressError::UnsupportedMethod(method) => {
+                write!(f, "Unsupported compression method: {method}")
+            }
+            CompressError::Corrupted => {
+                write!(f, "Compressed data is corrupted")
+            }
+            CompressError::LengthMismatch { declared, actual } => {
+                write!(f, "Decompressed length {actual} does not match the recorded length {declared}")
+            }
+            CompressError::TooLarge(length) => {
+                write!(f, "Payload is {length} bytes. Compressed payloads are limited to {MAX_DECOMPRESSED_LEN} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompressError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text() -> Vec<u8> {
+        "All work and no play makes Jack a dull boy. ".repeat(100).into_bytes()
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        let compressed = compress(&text(), Compression::Deflate).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < text().len() / 10);
+        assert_eq!(decompress(&compressed).unwrap(), text());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_round_trip() {
+        let compressed = compress(&text(), Compression::Zstd).unwrap();
+        assert_eq!(compressed[4], Compression::Zstd.id());
+        assert_eq!(decompress(&compressed).unwrap(), text());
+    }
+
+    #[test]
+    fn test_decompress_checks_length() {
+        let mut compressed = compress(&text(), Compression::Deflate).unwrap();
+        compressed[12] -= 1;
+        assert!(matches!(decompress(&compressed), Err(Error::Compress(CompressError::LengthMismatch { .. }))));
+
+        assert!(matches!(decompress(b"PNGZ\x09"), Err(Error::Compress(CompressError::Corrupted))));
+        assert!(matches!(decompress(b"plain"), Err(Error::Compress(CompressError::NotCompressed))));
+        assert!(Compression::from_str("lzma").is_err());
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let mut bomb = compress(&text(), Compression::Deflate).unwrap();
+        bomb[5..HEADER_LEN].copy_from_slice(&(MAX_DECOMPRESSED_LEN + 1).to_be_bytes());
+        assert!(matches!(decompress(&bomb), Err(Error::Compress(CompressError::Corrupted))));
+
+        bomb[5..HEADER_LEN].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(matches!(decompress(&bomb), Err(Error::Compress(CompressError::Corrupted))));
+    }
+}
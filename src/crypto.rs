@@ -0,0 +1,473 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::StaticSecret;
+
+use crate::chunk::read_u32;
+use crate::{Error, Result};
+
+/// Marks chunk data sealed by [`encrypt`].
+pub const MAGIC: [u8; 4] = *b"PNGC";
+pub const VERSION: u8 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const CHECK_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+
+/// magic, version, algorithm, three Argon2 parameters, salt, key check value and nonce.
+const HEADER_LEN: usize = 4 + 1 + 1 + 12 + SALT_LEN + CHECK_LEN + NONCE_LEN;
+
+/// Largest Argon2 costs [`decrypt`] accepts from a header: the defaults [`encrypt`] uses, so a crafted
+/// header cannot make it spend more memory or time than an honest one.
+pub const MAX_MEMORY_COST: u32 = Params::DEFAULT_M_COST;
+pub const MAX_TIME_COST: u32 = Params::DEFAULT_T_COST;
+pub const MAX_PARALLELISM: u32 = Params::DEFAULT_P_COST;
+
+/// An ephemeral public key and the wrapped file key, per recipient.
+const STANZA_LEN: usize = 32 + KEY_LEN + TAG_LEN;
+const WRAP_INFO: &[u8] = b"pngcoder X25519 file key";
+
+const PUBLIC_KEY_PREFIX: &str = "PNGC-PUBLIC-KEY-";
+const SECRET_KEY_PREFIX: &str = "PNGC-SECRET-KEY-";
+
+/// Key derivation and cipher used for a sealed payload.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum CipherSuite {
+    /// Argon2id key derivation, ChaCha20-Poly1305 encryption.
+    Argon2idChaCha20Poly1305,
+    /// A random file key wrapped for each X25519 recipient, ChaCha20-Poly1305 encryption.
+    X25519ChaCha20Poly1305
+}
+
+impl CipherSuite {
+    pub fn id(&self) -> u8 {
+        match self {
+            CipherSuite::Argon2idChaCha20Poly1305 => 1,
+            CipherSuite::X25519ChaCha20Poly1305 => 2
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(CipherSuite::Argon2idChaCha20Poly1305),
+            2 => Ok(CipherSuite::X25519ChaCha20Poly1305),
+            _ => Err(CryptoError::UnsupportedAlgorithm(id).into())
+        }
+    }
+}
+
+impl Display for CipherSuite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CipherSuite::Argon2idChaCha20Poly1305 => write!(f, "passphrase (Argon2id, ChaCha20-Poly1305)"),
+            CipherSuite::X25519ChaCha20Poly1305 => write!(f, "public key (X25519, ChaCha20-Poly1305)")
+        }
+    }
+}
+
+/// Whether `data` starts with the header written by [`encrypt`] or [`encrypt_to`].
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// The cipher suite named in the header of sealed data.
+pub fn cipher_suite(sealed: &[u8]) -> Result<CipherSuite> {
+    if !is_encrypted(sealed) { return Err(CryptoError::NotEncrypted.into()) }
+    if sealed.len() < 6 { return Err(CryptoError::Corrupted.into()) }
+    if sealed[4] != VERSION { return Err(CryptoError::UnsupportedVersion(sealed[4]).into()) }
+    CipherSuite::from_id(sealed[5])
+}
+
+/// An X25519 public key that payloads can be encrypted to with [`encrypt_to`].
+///
+/// Its text form is `PNGC-PUBLIC-KEY-` followed by 64 hex digits.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PublicKey(x25519_dalek::PublicKey);
+
+impl FromStr for PublicKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = parse_key(s, PUBLIC_KEY_PREFIX)?;
+        Ok(PublicKey(x25519_dalek::PublicKey::from(bytes)))
+    }
+}
+
+impl Display for PublicKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{PUBLIC_KEY_PREFIX}{}", to_hex(self.0.as_bytes()))
+    }
+}
+
+/// An X25519 private key that opens payloads sealed for its [`PublicKey`].
+///
+/// Its text form is `PNGC-SECRET-KEY-` followed by 64 hex digits.
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    pub fn generate() -> Self {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(x25519_dalek::PublicKey::from(&self.0))
+    }
+}
+
+impl FromStr for Identity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = parse_key(s, SECRET_KEY_PREFIX)?;
+        Ok(Identity(StaticSecret::from(bytes)))
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{SECRET_KEY_PREFIX}{}", to_hex(self.0.as_bytes()))
+    }
+}
+
+pub(crate) fn parse_key(s: &str, prefix: &str) -> Result<[u8; 32]> {
+    let invalid = || CryptoError::InvalidKey(prefix.trim_end_matches('-').to_string());
+    let hex = s.trim().strip_prefix(prefix).ok_or_else(invalid)?;
+    if hex.len() != 64 || !hex.is_ascii() { return Err(invalid().into()) }
+
+    let mut bytes = [0; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Seals `plaintext` with a key derived from `passphrase`.
+///
+/// The result starts with a versioned header holding the algorithm, KDF parameters, salt and nonce,
+/// followed by the ciphertext. The header is authenticated along with the ciphertext.
+pub fn encrypt(plaintext: &[u8], passphrase: &[u8]) -> Result<Vec<u8>> {
+    seal(plaintext, passphrase, Params::default())
+}
+
+/// Opens data sealed by [`encrypt`].
+///
+/// Fails with [`CryptoError::WrongPassphrase`] if the passphrase does not match, and with
+/// [`CryptoError::Corrupted`] if it does but the data was modified, or if the KDF parameters exceed
+/// [`MAX_MEMORY_COST`], [`MAX_TIME_COST`] or [`MAX_PARALLELISM`]. A damaged salt or KDF parameters
+/// within those limits cannot be told apart from a wrong passphrase.
+pub fn decrypt(sealed: &[u8], passphrase: &[u8]) -> Result<Vec<u8>> {
+    let suite = cipher_suite(sealed)?;
+    if suite != CipherSuite::Argon2idChaCha20Poly1305 { return Err(CryptoError::WrongCipherSuite(suite).into()) }
+    if sealed.len() < HEADER_LEN + TAG_LEN { return Err(CryptoError::Corrupted.into()) }
+
+    let (m_cost, t_cost, p_cost) = (read_u32(&sealed[6..10]), read_u32(&sealed[10..14]), read_u32(&sealed[14..18]));
+    if m_cost > MAX_MEMORY_COST || t_cost > MAX_TIME_COST || p_cost > MAX_PARALLELISM {
+        return Err(CryptoError::Corrupted.into())
+    }
+    let params = Params::new(m_cost, t_cost, p_cost, None).map_err(|_| CryptoError::Corrupted)?;
+    let (header, ciphertext) = sealed.split_at(HEADER_LEN);
+    let salt = &header[18..18 + SALT_LEN];
+    let check = &header[18 + SALT_LEN..18 + SALT_LEN + CHECK_LEN];
+    let nonce = &header[HEADER_LEN - NONCE_LEN..];
+
+    let (key, expected_check) = derive_key(passphrase, salt, params)?;
+    if check != expected_check { return Err(CryptoError::WrongPassphrase.into()) }
+
+    ChaCha20Poly1305::new(&key)
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| CryptoError::Corrupted.into())
+}
+
+/// Seals `plaintext` so that any of `recipients` can open it with their [`Identity`].
+///
+/// A random file key encrypts the payload. For each recipient the header holds an ephemeral
+/// public key and the file key wrapped with a key derived from the X25519 shared secret.
+pub fn encrypt_to(plaintext: &[u8], recipients: &[PublicKey]) -> Result<Vec<u8>> {
+    if recipients.is_empty() { return Err(CryptoError::NoRecipients.into()) }
+    let count = u16::try_from(recipients.len()).map_err(|_| CryptoError::TooManyRecipients(recipients.len()))?;
+
+    let file_key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut sealed = Vec::with_capacity(8 + recipients.len() * STANZA_LEN + NONCE_LEN + plaintext.len() + TAG_LEN);
+    sealed.extend(MAGIC);
+    sealed.push(VERSION);
+    sealed.push(CipherSuite::X25519ChaCha20Poly1305.id());
+    sealed.extend(count.to_be_bytes());
+
+    for recipient in recipients {
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let ephemeral_public = x25519_dalek::PublicKey::from(&ephemeral);
+        let wrap_key = wrap_key(&ephemeral.diffie_hellman(&recipient.0), &ephemeral_public, &recipient.0)?;
+
+        let wrapped = ChaCha20Poly1305::new(&wrap_key)
+            .encrypt(&Nonce::default(), file_key.as_slice())
+            .map_err(|_| CryptoError::Corrupted)?;
+        sealed.extend(ephemeral_public.as_bytes());
+        sealed.extend(wrapped);
+    }
+    sealed.extend(nonce);
+
+    let ciphertext = ChaCha20Poly1305::new(&file_key)
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &sealed })
+        .map_err(|_| CryptoError::Corrupted)?;
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+/// Opens data sealed by [`encrypt_to`] for the public key of `identity`.
+///
+/// Fails with [`CryptoError::NoMatchingIdentity`] if the data was not sealed for this identity.
+pub fn decrypt_with(sealed: &[u8], identity: &Identity) -> Result<Vec<u8>> {
+    let suite = cipher_suite(sealed)?;
+    if suite != CipherSuite::X25519ChaCha20Poly1305 { return Err(CryptoError::WrongCipherSuite(suite).into()) }
+    if sealed.len() < 8 { return Err(CryptoError::Corrupted.into()) }
+
+    let count = u16::from_be_bytes([sealed[6], sealed[7]]) as usize;
+    let header_len = 8 + count * STANZA_LEN + NONCE_LEN;
+    if sealed.len() < header_len + TAG_LEN { return Err(CryptoError::Corrupted.into()) }
+    let (header, ciphertext) = sealed.split_at(header_len);
+
+    let public_key = x25519_dalek::PublicKey::from(&identity.0);
+    let file_key = header[8..header_len - NONCE_LEN]
+        .chunks_exact(STANZA_LEN)
+        .find_map(|stanza| {
+            let mut ephemeral_public = [0; 32];
+            ephemeral_public.copy_from_slice(&stanza[..32]);
+            let ephemeral_public = x25519_dalek::PublicKey::from(ephemeral_public);
+            let wrap_key = wrap_key(&identity.0.diffie_hellman(&ephemeral_public), &ephemeral_public, &public_key).ok()?;
+            ChaCha20Poly1305::new(&wrap_key).decrypt(&Nonce::default(), &stanza[32..]).ok()
+        })
+        .ok_or(CryptoError::NoMatchingIdentity)?;
+
+    ChaCha20Poly1305::new(Key::from_slice(&file_key))
+        .decrypt(Nonce::from_slice(&header[header_len - NONCE_LEN..]), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| CryptoError::Corrupted.into())
+}
+
+fn wrap_key(
+    shared_secret: &x25519_dalek::SharedSecret,
+    ephemeral_public: &x25519_dalek::PublicKey,
+    recipient: &x25519_dalek::PublicKey
+) -> Result<Key> {
+    if !shared_secret.was_contributory() { return Err(CryptoError::InvalidKey("PNGC-PUBLIC-KEY".to_string()).into()) }
+
+    let salt = [ephemeral_public.as_bytes().as_slice(), recipient.as_bytes().as_slice()].concat();
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret.as_bytes())
+        .expand(WRAP_INFO, &mut key)
+        .map_err(|_| CryptoError::Corrupted)?;
+    Ok(key)
+}
+
+fn seal(plaintext: &[u8], passphrase: &[u8], params: Params) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let (key, check) = derive_key(passphrase, &salt, params.clone())?;
+
+    let mut sealed = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN);
+    sealed.extend(MAGIC);
+    sealed.push(VERSION);
+    sealed.push(CipherSuite::Argon2idChaCha20Poly1305.id());
+    sealed.extend(params.m_cost().to_be_bytes());
+    sealed.extend(params.t_cost().to_be_bytes());
+    sealed.extend(params.p_cost().to_be_bytes());
+    sealed.extend(salt);
+    sealed.extend(check);
+    sealed.extend(nonce);
+
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &sealed })
+        .map_err(|_| CryptoError::Corrupted)?;
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+/// Derives the cipher key and a check value that tells a wrong passphrase apart from corrupted data.
+fn derive_key(passphrase: &[u8], salt: &[u8], params: Params) -> Result<(Key, [u8; CHECK_LEN])> {
+    let mut output = [0; KEY_LEN + CHECK_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase, salt, &mut output)
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+
+    let mut check = [0; CHECK_LEN];
+    check.copy_from_slice(&output[KEY_LEN..]);
+    Ok((*Key::from_slice(&output[..KEY_LEN]), check))
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CryptoError {
+    NotEncrypted,
+    UnsupportedVersion(u8),
+    UnsupportedAlgorithm(u8),
+    WrongPassphrase,
+    /// The payload failed authentication or its header is malformed.
+    Corrupted,
+    KeyDerivation(String),
+    /// The data was sealed with a different cipher suite than the one used to open it.
+    WrongCipherSuite(CipherSuite),
+    /// A key is not in its text form. Holds the expected prefix.
+    InvalidKey(String),
+    NoRecipients,
+    TooManyRecipients(usize),
+    /// None of the recipients in the header match the identity.
+    NoMatchingIdentity
+}
+
+impl Display for CryptoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CryptoError::NotEncrypted => {
+                write!(f, "Chunk data is not encrypted")
+            }
+            CryptoError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported encryption header version: {version}")
+            }
+            CryptoError::UnsupportedAlgorithm(id) => {
+                write!(f, "Unsupported encryption algorithm: {id}")
+            }
+            CryptoError::WrongPassphrase => {
+                write!(f, "Wrong passphrase")
+            }
+            CryptoError::Corrupted => {
+                write!(f, "Encrypted data is corrupted")
+            }
+            CryptoError::KeyDerivation(message) => {
+                write!(f, "Key derivation failed: {message}")
+            }
+            CryptoError::WrongCipherSuite(suite) => {
+                write!(f, "Data is encrypted with {suite}")
+            }
+            CryptoError::InvalidKey(prefix) => {
+                write!(f, "Invalid key. Expected {prefix}- followed by 64 hex digits")
+            }
+            CryptoError::NoRecipients => {
+                write!(f, "At least one recipient is required")
+            }
+            CryptoError::TooManyRecipients(count) => {
+                write!(f, "Too many recipients: {count}. Max: {}", u16::MAX)
+            }
+            CryptoError::NoMatchingIdentity => {
+                write!(f, "Data was not encrypted to this identity")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn fast_params() -> Params {
+        Params::new(64, 1, 1, None).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sealed = seal(b"This is a secret message!", b"hunter2", fast_params()).unwrap();
+        assert!(is_encrypted(&sealed));
+        assert_eq!(sealed.len(), HEADER_LEN + 25 + TAG_LEN);
+        assert_eq!(decrypt(&sealed, b"hunter2").unwrap(), b"This is a secret message!");
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let sealed = seal(b"secret", b"hunter2", fast_params()).unwrap();
+        assert!(matches!(decrypt(&sealed, b"hunter3"), Err(Error::Crypto(CryptoError::WrongPassphrase))));
+    }
+
+    #[test]
+    fn test_corrupted_ciphertext() {
+        let mut sealed = seal(b"secret", b"hunter2", fast_params()).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(matches!(decrypt(&sealed, b"hunter2"), Err(Error::Crypto(CryptoError::Corrupted))));
+
+        let mut sealed = seal(b"secret", b"hunter2", fast_params()).unwrap();
+        sealed[HEADER_LEN - 1] ^= 1;
+        assert!(matches!(decrypt(&sealed, b"hunter2"), Err(Error::Crypto(CryptoError::Corrupted))));
+
+        let sealed = seal(b"secret", b"hunter2", fast_params()).unwrap();
+        assert!(matches!(decrypt(&sealed[..HEADER_LEN], b"hunter2"), Err(Error::Crypto(CryptoError::Corrupted))));
+    }
+
+    #[test]
+    fn test_kdf_cost_limits() {
+        let sealed = seal(b"secret", b"hunter2", fast_params()).unwrap();
+        for (field, limit) in [(6, MAX_MEMORY_COST), (10, MAX_TIME_COST), (14, MAX_PARALLELISM)] {
+            let mut crafted = sealed.clone();
+            crafted[field..field + 4].copy_from_slice(&(limit + 1).to_be_bytes());
+            assert!(matches!(decrypt(&crafted, b"hunter2"), Err(Error::Crypto(CryptoError::Corrupted))), "field at {field}");
+        }
+
+        let mut crafted = sealed.clone();
+        crafted[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(decrypt(&crafted, b"hunter2"), Err(Error::Crypto(CryptoError::Corrupted))));
+    }
+
+    #[test]
+    fn test_recipients_round_trip() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let sealed = encrypt_to(b"team secret", &[alice.public_key(), bob.public_key()]).unwrap();
+
+        assert_eq!(cipher_suite(&sealed).unwrap(), CipherSuite::X25519ChaCha20Poly1305);
+        assert_eq!(decrypt_with(&sealed, &alice).unwrap(), b"team secret");
+        assert_eq!(decrypt_with(&sealed, &bob).unwrap(), b"team secret");
+
+        let eve = Identity::generate();
+        assert!(matches!(decrypt_with(&sealed, &eve), Err(Error::Crypto(CryptoError::NoMatchingIdentity))));
+        assert!(matches!(decrypt(&sealed, b"hunter2"), Err(Error::Crypto(CryptoError::WrongCipherSuite(_)))));
+    }
+
+    #[test]
+    fn test_recipients_corrupted() {
+        let alice = Identity::generate();
+        let mut sealed = encrypt_to(b"team secret", &[alice.public_key()]).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(matches!(decrypt_with(&sealed, &alice), Err(Error::Crypto(CryptoError::Corrupted))));
+        assert!(matches!(encrypt_to(b"", &[]), Err(Error::Crypto(CryptoError::NoRecipients))));
+    }
+
+    #[test]
+    fn test_key_text_round_trip() {
+        let identity = Identity::generate();
+        let parsed = Identity::from_str(&identity.to_string()).unwrap();
+        assert_eq!(parsed.public_key(), identity.public_key());
+
+        let public_key = identity.public_key().to_string();
+        assert!(public_key.starts_with("PNGC-PUBLIC-KEY-"));
+        assert_eq!(PublicKey::from_str(&public_key).unwrap(), identity.public_key());
+
+        assert!(PublicKey::from_str(&identity.to_string()).is_err());
+        assert!(PublicKey::from_str("PNGC-PUBLIC-KEY-abc").is_err());
+    }
+
+    #[test]
+    fn test_header_checks() {
+        assert!(matches!(decrypt(b"plain text", b"hunter2"), Err(Error::Crypto(CryptoError::NotEncrypted))));
+
+        let mut sealed = seal(b"secret", b"hunter2", fast_params()).unwrap();
+        sealed[4] = 2;
+        assert!(matches!(decrypt(&sealed, b"hunter2"), Err(Error::Crypto(CryptoError::UnsupportedVersion(2)))));
+    }
+}
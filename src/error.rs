@@ -0,0 +1,185 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::str::Utf8Error;
+
+use crate::chunk::ChunkError;
+use crate::chunk_type::ChunkTypeError;
+use crate::compress::CompressError;
+use crate::crypto::CryptoError;
+use crate::fragment::FragmentError;
+use crate::image_header::HeaderError;
+use crate::pixels::PixelError;
+use crate::png::PngError;
+use crate::signature::SignatureError;
+use crate::stego::StegoError;
+use crate::text::TextError;
+
+/// Every error returned by this crate.
+///
+/// The wrapped module errors are available through [`std::error::Error::source`] as well as
+/// by matching on the variant.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    Io(io::Error),
+    Utf8(Utf8Error),
+    ChunkType(ChunkTypeError),
+    /// A chunk failed to parse. `offset` is the byte offset of the chunk's length field,
+    /// relative to the start of the parsed input.
+    Chunk { offset: usize, source: ChunkError },
+    Png(PngError),
+    Header(HeaderError),
+    Pixel(PixelError),
+    Crypto(CryptoError),
+    Compress(CompressError),
+    Fragment(FragmentError),
+    Signature(SignatureError),
+    Stego(StegoError),
+    Text(TextError)
+}
+
+impl Error {
+    pub(crate) fn at_offset(self, base: usize) -> Self {
+        match self {
+            Error::Chunk { offset, source } => Error::Chunk { offset: base + offset, source },
+            other => other
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(_) => write!(f, "I/O error"),
+            Error::Utf8(_) => write!(f, "Chunk data is not valid UTF-8"),
+            Error::ChunkType(_) => write!(f, "Invalid chunk type"),
+            Error::Chunk { offset, .. } => write!(f, "Invalid chunk at byte {offset}"),
+            Error::Png(_) => write!(f, "Invalid PNG"),
+            Error::Header(_) => write!(f, "Invalid image header"),
+            Error::Pixel(_) => write!(f, "Invalid image data"),
+            Error::Crypto(_) => write!(f, "Encryption error"),
+            Error::Compress(_) => write!(f, "Compression error"),
+            Error::Fragment(_) => write!(f, "Split payload error"),
+            Error::Signature(_) => write!(f, "Signature error"),
+            Error::Stego(_) => write!(f, "Pixel payload error"),
+            Error::Text(_) => write!(f, "Text metadata error")
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Utf8(e) => Some(e),
+            Error::ChunkType(e) => Some(e),
+            Error::Chunk { source, .. } => Some(source),
+            Error::Png(e) => Some(e),
+            Error::Header(e) => Some(e),
+            Error::Pixel(e) => Some(e),
+            Error::Crypto(e) => Some(e),
+            Error::Compress(e) => Some(e),
+            Error::Fragment(e) => Some(e),
+            Error::Signature(e) => Some(e),
+            Error::Stego(e) => Some(e),
+            Error::Text(e) => Some(e)
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(value: Utf8Error) -> Self {
+        Error::Utf8(value)
+    }
+}
+
+impl From<ChunkTypeError> for Error {
+    fn from(value: ChunkTypeError) -> Self {
+        Error::ChunkType(value)
+    }
+}
+
+impl From<ChunkError> for Error {
+    fn from(value: ChunkError) -> Self {
+        Error::Chunk { offset: 0, source: value }
+    }
+}
+
+impl From<PngError> for Error {
+    fn from(value: PngError) -> Self {
+        Error::Png(value)
+    }
+}
+
+impl From<HeaderError> for Error {
+    fn from(value: HeaderError) -> Self {
+        Error::Header(value)
+    }
+}
+
+impl From<PixelError> for Error {
+    fn from(value: PixelError) -> Self {
+        Error::Pixel(value)
+    }
+}
+
+impl From<CryptoError> for Error {
+    fn from(value: CryptoError) -> Self {
+        Error::Crypto(value)
+    }
+}
+
+impl From<CompressError> for Error {
+    fn from(value: CompressError) -> Self {
+        Error::Compress(value)
+    }
+}
+
+impl From<FragmentError> for Error {
+    fn from(value: FragmentError) -> Self {
+        Error::Fragment(value)
+    }
+}
+
+impl From<SignatureError> for Error {
+    fn from(value: SignatureError) -> Self {
+        Error::Signature(value)
+    }
+}
+
+impl From<StegoError> for Error {
+    fn from(value: StegoError) -> Self {
+        Error::Stego(value)
+    }
+}
+
+impl From<TextError> for Error {
+    fn from(value: TextError) -> Self {
+        Error::Text(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_source_chain() {
+        let error = Error::from(ChunkError::InvalidChunkLength(3)).at_offset(8);
+        assert!(matches!(error, Error::Chunk { offset: 8, source: ChunkError::InvalidChunkLength(3) }));
+        assert_eq!(error.source().unwrap().to_string(), ChunkError::InvalidChunkLength(3).to_string());
+    }
+
+    #[test]
+    fn test_error_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Error>();
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs::File;
+use std::ops::Deref;
+use std::path::Path;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use crate::png::PngRef;
+use crate::Result;
+
+/// Files at least this large are memory-mapped by [`PngFile::open`] instead of read into memory.
+pub const MMAP_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// The bytes of a PNG file, either read into memory or memory-mapped.
+///
+/// Parse it with [`PngFile::png`] to get a [`PngRef`] borrowing from the file.
+pub enum PngFile {
+    Read(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(Mmap)
+}
+
+impl PngFile {
+    /// Opens a file, memory-mapping it if it is at least [`MMAP_THRESHOLD`] bytes long.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with_threshold(path, MMAP_THRESHOLD)
+    }
+
+    /// Opens a file, memory-mapping it if it is at least `threshold` bytes long.
+    ///
+    /// Without the `mmap` feature the file is always read into memory.
+    pub fn open_with_threshold<P: AsRef<Path>>(path: P, threshold: u64) -> Result<Self> {
+        let file = File::open(path)?;
+
+        #[cfg(feature = "mmap")]
+        if file.metadata()?.len() >= threshold {
+            // SAFETY: the mapping is read-only. Another process truncating or rewriting the file
+            // while it is mapped is outside what this crate can guard against, as with any mmap.
+            let mmap = unsafe { Mmap::map(&file)? };
+            return Ok(PngFile::Mapped(mmap));
+        }
+        #[cfg(not(feature = "mmap"))]
+        let _ = threshold;
+
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut &file, &mut bytes)?;
+        Ok(PngFile::Read(bytes))
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        !matches!(self, PngFile::Read(_))
+    }
+
+    /// Parses the file's chunks, checking every CRC whether or not the file is mapped.
+    ///
+    /// To walk the chunk headers of a mapped file without touching the pages that hold chunk data,
+    /// use [`PngRef::scan`] and [`ChunkRef::verify`](crate::chunk::ChunkRef::verify) the chunks you read.
+    pub fn png(&self) -> Result<PngRef<'_>> {
+        PngRef::try_from(&**self)
+    }
+}
+
+impl Deref for PngFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            PngFile::Read(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            PngFile::Mapped(mmap) => mmap
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_read() {
+        let file = PngFile::open("dice.png").unwrap();
+        assert!(!file.is_mapped());
+        assert_eq!(file.png().unwrap().chunks().len(), 10);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open_mapped() {
+        let file = PngFile::open_with_threshold("dice.png", 0).unwrap();
+        assert!(file.is_mapped());
+        assert_eq!(&*file, std::fs::read("dice.png").unwrap().as_slice());
+
+        let png = file.png().unwrap();
+        assert_eq!(png.chunks().len(), 10);
+        assert!(png.chunks().iter().all(|c| c.verify().is_ok()));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open_mapped_checks_crc() {
+        let mut bytes = std::fs::read("dice.png").unwrap();
+        *bytes.last_mut().unwrap() ^= 0xFF;
+        let path = std::env::temp_dir().join(format!("pngcoder-file-test-{}.png", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mapped = PngFile::open_with_threshold(&path, 0).unwrap();
+        let read = PngFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(mapped.is_mapped() && !read.is_mapped());
+        assert!(mapped.png().is_err());
+        assert!(read.png().is_err());
+        assert_eq!(PngRef::scan(&mapped).unwrap().chunks().len(), 10);
+    }
+}
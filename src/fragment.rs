@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use crc::Crc;
+
+use crate::chunk::read_u32;
+use crate::crypto::to_hex;
+use crate::Result;
+
+/// Marks chunk data written by [`split`].
+pub const MAGIC: [u8; 4] = *b"PNGF";
+
+/// magic, message ID, sequence number, fragment count, payload length and payload CRC.
+pub const HEADER_LEN: usize = 4 + 8 + 4 + 4 + 8 + 4;
+
+/// Most missing sequence numbers a [`FragmentError::Missing`] lists.
+const MAX_LISTED_MISSING: usize = 20;
+
+/// One piece of a payload split across several chunks.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fragment<'a> {
+    /// Shared by every fragment of one payload.
+    pub message_id: u64,
+    /// Position of this fragment, from 1 to `total`.
+    pub sequence: u32,
+    pub total: u32,
+    /// Length of the whole payload.
+    pub payload_len: u64,
+    /// CRC-32 of the whole payload.
+    pub payload_crc: u32,
+    pub data: &'a [u8]
+}
+
+impl<'a> Fragment<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if !is_fragment(data) { return Err(FragmentError::NotFragment.into()) }
+        if data.len() < HEADER_LEN { return Err(FragmentError::Malformed.into()) }
+
+        let fragment = Fragment {
+            message_id: u64::from_be_bytes(data[4..12].try_into().unwrap()),
+            sequence: read_u32(&data[12..16]),
+            total: read_u32(&data[16..20]),
+            payload_len: u64::from_be_bytes(data[20..28].try_into().unwrap()),
+            payload_crc: read_u32(&data[28..32]),
+            data: &data[HEADER_LEN..]
+        };
+        if fragment.total == 0 || fragment.sequence == 0 || fragment.sequence > fragment.total {
+            return Err(FragmentError::Malformed.into());
+        }
+        Ok(fragment)
+    }
+}
+
+/// Whether `data` starts with the header written by [`split`].
+pub fn is_fragment(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Splits `payload` into chunk data of at most `fragment_len` payload bytes each.
+///
+/// Every fragment carries a random message ID shared by the whole payload, its sequence number,
+/// the fragment count and the length and CRC of the whole payload, checked by [`reassemble`].
+pub fn split(payload: &[u8], fragment_len: usize) -> Result<Vec<Vec<u8>>> {
+    if fragment_len == 0 { return Err(FragmentError::InvalidFragmentLength.into()) }
+
+    let pieces: Vec<&[u8]> = if payload.is_empty() { vec![&[]] } else { payload.chunks(fragment_len).collect() };
+    let total = u32::try_from(pieces.len()).map_err(|_| FragmentError::TooManyFragments(pieces.len()))?;
+    let message_id = OsRng.next_u64();
+    let payload_crc = payload_crc(payload);
+
+    Ok(pieces
+        .iter()
+        .zip(1..)
+        .map(|(piece, sequence): (&&[u8], u32)| {
+            let mut data = Vec::with_capacity(HEADER_LEN + piece.len());
+            data.extend(MAGIC);
+            data.extend(message_id.to_be_bytes());
+            data.extend(sequence.to_be_bytes());
+            data.extend(total.to_be_bytes());
+            data.extend((payload.len() as u64).to_be_bytes());
+            data.extend(payload_crc.to_be_bytes());
+            data.extend(*piece);
+            data
+        })
+        .collect())
+}
+
+/// Orders and joins the fragments of one payload, checking that none are missing or corrupted.
+///
+/// `fragments` may hold several payloads; `message_id` picks one, and is required in that case.
+pub fn reassemble<'a, I>(fragments: I, message_id: Option<u64>) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = &'a [u8]>
+{
+    let mut messages: BTreeMap<u64, Vec<Fragment>> = BTreeMap::new();
+    for data in fragments {
+        let fragment = Fragment::parse(data)?;
+        messages.entry(fragment.message_id).or_default().push(fragment);
+    }
+
+    let message_id = match message_id {
+        Some(id) => id,
+        None if messages.len() == 1 => *messages.keys().next().unwrap(),
+        None if messages.is_empty() => return Err(FragmentError::NotFragment.into()),
+        None => return Err(FragmentError::AmbiguousMessage(messages.keys().map(|&id| format_id(id)).collect()).into())
+    };
+    let fragments = messages.remove(&message_id).ok_or(FragmentError::MessageNotFound(format_id(message_id)))?;
+
+    let first = &fragments[0];
+    let mut ordered: BTreeMap<u32, &[u8]> = BTreeMap::new();
+    for fragment in &fragments {
+        if (fragment.total, fragment.payload_len, fragment.payload_crc) != (first.total, first.payload_len, first.payload_crc) {
+            return Err(FragmentError::Inconsistent(format_id(message_id)).into());
+        }
+        match ordered.insert(fragment.sequence, fragment.data) {
+            Some(previous) if previous != fragment.data => {
+                return Err(FragmentError::Conflict { message_id: format_id(message_id), sequence: fragment.sequence }.into());
+            }
+            _ => {}
+        }
+    }
+
+    // The count comes from the file, so it must fit the payload length before the gaps are listed
+    let plausible = match fragments.iter().find(|f| f.sequence < f.total) {
+        // Every fragment but the last holds exactly the fragment length
+        Some(full) => !full.data.is_empty() && first.payload_len.div_ceil(full.data.len() as u64) == first.total as u64,
+        None => first.total as u64 <= first.payload_len.max(1)
+    };
+    if !plausible {
+        return Err(FragmentError::Inconsistent(format_id(message_id)).into());
+    }
+
+    let count = first.total - ordered.len() as u32;
+    if count > 0 {
+        let missing = (1..=first.total).filter(|s| !ordered.contains_key(s)).take(MAX_LISTED_MISSING).collect();
+        return Err(FragmentError::Missing { message_id: format_id(message_id), missing, count, total: first.total }.into());
+    }
+
+    let payload = ordered.into_values().collect::<Vec<_>>().concat();
+    if payload.len() as u64 != first.payload_len || payload_crc(&payload) != first.payload_crc {
+        return Err(FragmentError::Corrupted(format_id(message_id)).into());
+    }
+    Ok(payload)
+}
+
+/// A message ID as 16 hex digits, the form [`FragmentError`] reports it in.
+pub fn format_id(message_id: u64) -> String {
+    to_hex(&message_id.to_be_bytes())
+}
+
+fn payload_crc(payload: &[u8]) -> u32 {
+    Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(payload)
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FragmentError {
+    NotFragment,
+    Malformed,
+    InvalidFragmentLength,
+    TooManyFragments(usize),
+    /// The chunks hold fragments of several payloads. Holds their message IDs.
+    AmbiguousMessage(Vec<String>),
+    MessageNotFound(String),
+    /// Fragments of one payload disagree on the fragment count or payload length.
+    Inconsistent(String),
+    /// Two different fragments share a sequence number.
+    Conflict { message_id: String, sequence: u32 },
+    /// `count` fragments are missing, of which `missing` lists the first few.
+    Missing { message_id: String, missing: Vec<u32>, count: u32, total: u32 },
+    /// The reassembled payload does not match the recorded length and CRC.
+    Corrupted(String)
+}
+
+impl Display for FragmentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FragmentError::NotFragment => {
+                write!(f, "Data is not a payload fragment")
+            }
+            FragmentError::Malformed => {
+                write!(f, "Fragment header is malformed")
+            }
+            FragmentError::InvalidFragmentLength => {
+                write!(f, "Fragment length must be at least 1 byte")
+            }
+            FragmentError::TooManyFragments(count) => {
+                write!(f, "Too many fragments: {count}. Max: {}", u32::MAX)
+            }
+            FragmentError::AmbiguousMessage(ids) => {
+                write!(f, "Fragments of {} messages found: {}. Choose one by message ID", ids.len(), ids.join(", "))
+            }
+            FragmentError::MessageNotFound(id) => {
+                write!(f, "No fragments of message {id} found")
+            }
+            FragmentError::Inconsistent(id) => {
+                write!(f, "Fragments of message {id} disagree on the payload length or fragment count")
+            }
+            FragmentError::Conflict { message_id, sequence } => {
+                write!(f, "Message {message_id} has two different fragments numbered {sequence}")
+            }
+            FragmentError::Missing { message_id, missing, count, total } => {
+                let listed: Vec<String> = missing.iter().map(u32::to_string).collect();
+                if *count as usize > missing.len() {
+                    write!(f, "Message {message_id} is missing {count} of {total} fragments, starting with {}", listed.join(", "))
+                } else {
+                    write!(f, "Message {message_id} is missing fragment(s) {} of {total}", listed.join(", "))
+                }
+            }
+            FragmentError::Corrupted(id) => {
+                write!(f, "Reassembled message {id} does not match its checksum")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FragmentError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn payload() -> Vec<u8> {
+        (0..=255).cycle().take(1000).collect()
+    }
+
+    #[test]
+    fn test_split_and_reassemble() {
+        let fragments = split(&payload(), 300).unwrap();
+        assert_eq!(fragments.len(), 4);
+        assert_eq!(fragments[3].len(), HEADER_LEN + 100);
+
+        let fragment = Fragment::parse(&fragments[1]).unwrap();
+        assert_eq!((fragment.sequence, fragment.total, fragment.payload_len), (2, 4, 1000));
+
+        // Order in the file does not matter
+        let shuffled = [&fragments[2], &fragments[0], &fragments[3], &fragments[1]];
+        assert_eq!(reassemble(shuffled.iter().map(|f| f.as_slice()), None).unwrap(), payload());
+        assert_eq!(reassemble(split(b"", 10).unwrap().iter().map(|f| f.as_slice()), None).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_reassemble_missing_fragments() {
+        let fragments = split(&payload(), 100).unwrap();
+        let present = fragments.iter().enumerate().filter(|(i, _)| ![2, 6].contains(i)).map(|(_, f)| f.as_slice());
+        let result = reassemble(present, None);
+        assert!(matches!(result, Err(Error::Fragment(FragmentError::Missing { ref missing, count: 2, total: 10, .. })) if missing == &[3, 7]));
+    }
+
+    #[test]
+    fn test_reassemble_implausible_total() {
+        let mut fragment = split(&payload(), 100).unwrap().swap_remove(0);
+        fragment[16..20].copy_from_slice(&u32::MAX.to_be_bytes());
+        let result = reassemble([fragment.as_slice()], None);
+        assert!(matches!(result, Err(Error::Fragment(FragmentError::Inconsistent(_)))));
+
+        // The last fragment alone only bounds the count by the payload length
+        let mut fragment = split(&payload(), 100).unwrap().pop().unwrap();
+        fragment[16..20].copy_from_slice(&1001u32.to_be_bytes());
+        fragment[12..16].copy_from_slice(&1001u32.to_be_bytes());
+        let result = reassemble([fragment.as_slice()], None);
+        assert!(matches!(result, Err(Error::Fragment(FragmentError::Inconsistent(_)))));
+
+        let fragments = split(&payload(), 1).unwrap();
+        match reassemble([fragments[999].as_slice()], None) {
+            Err(Error::Fragment(error @ FragmentError::Missing { count: 999, .. })) => {
+                assert!(error.to_string().ends_with("is missing 999 of 1000 fragments, starting with 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20"));
+            }
+            _ => panic!("expected missing fragments")
+        }
+    }
+
+    #[test]
+    fn test_reassemble_several_messages() {
+        let first = split(b"first message", 5).unwrap();
+        let second = split(b"second message", 5).unwrap();
+        let all = || first.iter().chain(second.iter()).map(|f| f.as_slice());
+
+        assert!(matches!(reassemble(all(), None), Err(Error::Fragment(FragmentError::AmbiguousMessage(_)))));
+        let id = Fragment::parse(&second[0]).unwrap().message_id;
+        assert_eq!(reassemble(all(), Some(id)).unwrap(), b"second message");
+    }
+
+    #[test]
+    fn test_reassemble_corrupted() {
+        let mut fragments = split(&payload(), 300).unwrap();
+        let last = fragments[1].len() - 1;
+        fragments[1][last] ^= 1;
+        let result = reassemble(fragments.iter().map(|f| f.as_slice()), None);
+        assert!(matches!(result, Err(Error::Fragment(FragmentError::Corrupted(_)))));
+    }
+}
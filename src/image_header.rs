@@ -0,0 +1,314 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::chunk::{read_u32, Chunk};
+use crate::chunk_type::ChunkType;
+use crate::{Error, Result};
+
+/// How pixel samples are laid out, from the IHDR colour type field.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba
+}
+
+impl ColorType {
+    /// Samples per pixel.
+    pub fn channels(&self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4
+        }
+    }
+
+    /// Bit depths the specification allows for this colour type.
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16]
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(HeaderError::InvalidColorType(value).into())
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "Grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "Indexed",
+            ColorType::GrayscaleAlpha => "Grayscale + alpha",
+            ColorType::Rgba => "RGBA"
+        };
+        write!(f, "{name} ({})", self.value())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InterlaceMethod {
+    None,
+    Adam7
+}
+
+impl InterlaceMethod {
+    pub fn value(&self) -> u8 {
+        match self {
+            InterlaceMethod::None => 0,
+            InterlaceMethod::Adam7 => 1
+        }
+    }
+}
+
+/// The image properties stored in the IHDR chunk.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ImageHeader {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    interlace_method: InterlaceMethod
+}
+
+impl ImageHeader {
+    /// Length of the IHDR chunk data.
+    pub const LENGTH: usize = 13;
+
+    /// Creates a header, checking the dimensions and the bit depth against the colour type.
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        interlace_method: InterlaceMethod
+    ) -> Result<Self> {
+        if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+            return Err(HeaderError::InvalidDimensions { width, height }.into());
+        }
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(HeaderError::InvalidBitDepth { bit_depth, color_type }.into());
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlace_method
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    /// Always 0 (deflate), the only method the specification defines.
+    pub fn compression_method(&self) -> u8 {
+        0
+    }
+
+    /// Always 0 (adaptive filtering), the only method the specification defines.
+    pub fn filter_method(&self) -> u8 {
+        0
+    }
+
+    pub fn interlace_method(&self) -> InterlaceMethod {
+        self.interlace_method
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bit_depth as usize * self.color_type.channels() as usize
+    }
+
+    /// Serialized IHDR data.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.width
+            .to_be_bytes()
+            .iter()
+            .chain(self.height.to_be_bytes().iter())
+            .chain([
+                self.bit_depth,
+                self.color_type.value(),
+                self.compression_method(),
+                self.filter_method(),
+                self.interlace_method.value()
+            ].iter())
+            .copied()
+            .collect()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), self.as_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for ImageHeader {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        if value.len() != Self::LENGTH {
+            return Err(HeaderError::InvalidLength(value.len()).into());
+        }
+
+        let color_type = ColorType::try_from(value[9])?;
+        if value[10] != 0 { return Err(HeaderError::InvalidCompressionMethod(value[10]).into()) }
+        if value[11] != 0 { return Err(HeaderError::InvalidFilterMethod(value[11]).into()) }
+        let interlace_method = match value[12] {
+            0 => InterlaceMethod::None,
+            1 => InterlaceMethod::Adam7,
+            other => return Err(HeaderError::InvalidInterlaceMethod(other).into())
+        };
+
+        ImageHeader::new(read_u32(&value[0..4]), read_u32(&value[4..8]), value[8], color_type, interlace_method)
+    }
+}
+
+impl Display for ImageHeader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "ImageHeader {{")?;
+        writeln!(f, "  Width: {}", self.width)?;
+        writeln!(f, "  Height: {}", self.height)?;
+        writeln!(f, "  Bit depth: {}", self.bit_depth)?;
+        writeln!(f, "  Colour type: {}", self.color_type)?;
+        writeln!(f, "  Compression method: {}", self.compression_method())?;
+        writeln!(f, "  Filter method: {}", self.filter_method())?;
+        writeln!(f, "  Interlace method: {:?} ({})", self.interlace_method, self.interlace_method.value())?;
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HeaderError {
+    /// The first chunk is not IHDR.
+    MissingIhdr,
+    InvalidLength(usize),
+    InvalidDimensions { width: u32, height: u32 },
+    InvalidColorType(u8),
+    InvalidBitDepth { bit_depth: u8, color_type: ColorType },
+    InvalidCompressionMethod(u8),
+    InvalidFilterMethod(u8),
+    InvalidInterlaceMethod(u8)
+}
+
+impl Display for HeaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderError::MissingIhdr => {
+                write!(f, "The first chunk is not IHDR")
+            }
+            HeaderError::InvalidLength(length) => {
+                write!(f, "Invalid IHDR length: {length}. Expected: {}", ImageHeader::LENGTH)
+            }
+            HeaderError::InvalidDimensions { width, height } => {
+                write!(f, "Invalid image dimensions: {width}x{height}")
+            }
+            HeaderError::InvalidColorType(color_type) => {
+                write!(f, "Invalid colour type: {color_type}")
+            }
+            HeaderError::InvalidBitDepth { bit_depth, color_type } => {
+                write!(f, "Bit depth {bit_depth} is not allowed for colour type {color_type}. Allowed: {:?}", color_type.allowed_bit_depths())
+            }
+            HeaderError::InvalidCompressionMethod(method) => {
+                write!(f, "Invalid compression method: {method}")
+            }
+            HeaderError::InvalidFilterMethod(method) => {
+                write!(f, "Invalid filter method: {method}")
+            }
+            HeaderError::InvalidInterlaceMethod(method) => {
+                write!(f, "Invalid interlace method: {method}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_bytes(bit_depth: u8, color_type: u8) -> Vec<u8> {
+        let mut bytes = vec![0, 0, 0, 50, 0, 0, 0, 40];
+        bytes.extend([bit_depth, color_type, 0, 0, 1]);
+        bytes
+    }
+
+    #[test]
+    fn test_header_from_bytes() {
+        let header = ImageHeader::try_from(header_bytes(8, 6).as_slice()).unwrap();
+        assert_eq!(header.width(), 50);
+        assert_eq!(header.height(), 40);
+        assert_eq!(header.bit_depth(), 8);
+        assert_eq!(header.color_type(), ColorType::Rgba);
+        assert_eq!(header.interlace_method(), InterlaceMethod::Adam7);
+        assert_eq!(header.bits_per_pixel(), 32);
+        assert_eq!(header.as_bytes(), header_bytes(8, 6));
+    }
+
+    #[test]
+    fn test_bit_depth_color_type_combinations() {
+        for (color_type, valid) in [(0, &[1, 2, 4, 8, 16][..]), (2, &[8, 16]), (3, &[1, 2, 4, 8]), (4, &[8, 16]), (6, &[8, 16])] {
+            for bit_depth in [1, 2, 3, 4, 8, 16, 32] {
+                let header = ImageHeader::try_from(header_bytes(bit_depth, color_type).as_slice());
+                assert_eq!(header.is_ok(), valid.contains(&bit_depth), "colour type {color_type}, bit depth {bit_depth}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_header_fields() {
+        assert!(ImageHeader::try_from(&header_bytes(8, 6)[..12]).is_err());
+        assert!(ImageHeader::try_from(header_bytes(8, 5).as_slice()).is_err());
+
+        let mut bytes = header_bytes(8, 6);
+        bytes[3] = 0;
+        assert!(matches!(ImageHeader::try_from(bytes.as_slice()), Err(Error::Header(HeaderError::InvalidDimensions { width: 0, .. }))));
+
+        let mut bytes = header_bytes(8, 6);
+        bytes[12] = 2;
+        assert!(matches!(ImageHeader::try_from(bytes.as_slice()), Err(Error::Header(HeaderError::InvalidInterlaceMethod(2)))));
+    }
+}
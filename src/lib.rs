@@ -0,0 +1,47 @@
+//! Encode and decode data hidden in PNG chunks.
+//!
+//! A [`Png`](png::Png) is a list of [`Chunk`](chunk::Chunk)s, each tagged with a
+//! four-letter [`ChunkType`](chunk_type::ChunkType).
+//!
+//! ```
+//! use std::str::FromStr;
+//! use pngcoder::chunk::Chunk;
+//! use pngcoder::chunk_type::ChunkType;
+//! use pngcoder::png::{ChunkPosition, Png};
+//!
+//! let mut png = Png::from_chunks(vec![]);
+//! let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"secret".to_vec());
+//! png.insert_chunk(chunk, ChunkPosition::BeforeIend).unwrap();
+//! assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"secret");
+//! ```
+
+// The original chunk type and PNG code and tests are kept as written, so their lints are allowed here
+#[allow(clippy::manual_is_ascii_check)]
+#[cfg_attr(test, allow(unused_variables))]
+pub mod chunk_type;
+pub mod chunk;
+#[cfg_attr(test, allow(clippy::vec_init_then_push, clippy::iter_cloned_collect))]
+pub mod png;
+pub mod parse;
+pub mod repair;
+pub mod carve;
+pub mod pixels;
+pub mod image_header;
+pub mod validate;
+pub mod compress;
+pub mod crypto;
+pub mod fragment;
+pub mod signature;
+pub mod stego;
+pub mod analyze;
+pub mod text;
+pub mod stream;
+pub mod file;
+pub mod error;
+
+pub use error::Error;
+
+/// Largest data length a single chunk may declare, 2^31-1 per the PNG specification.
+pub const MAX_CHUNK_LEN: u32 = 2147483647;
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,18 +1,40 @@
-extern crate core;
+use std::error::Error as _;
+use std::process::ExitCode;
+use pngcoder::Error;
+use pngcoder::crypto::CryptoError;
+use pngcoder::png::PngError;
+use pngcoder::signature::SignatureError;
+use pngcoder::stego::StegoError;
+use pngcoder::text::TextError;
 
-mod chunk_type;
-mod chunk;
-mod png;
 mod args;
 mod commands;
 
-pub const MAX_CHUNK_LEN: u32 = 2147483648;
-
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+fn main() -> ExitCode {
+    match commands::Handler::handle() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprint!("Handler error: {e}");
+            let mut source = e.source();
+            while let Some(inner) = source {
+                eprint!(": {inner}");
+                source = inner.source();
+            }
+            eprintln!();
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
 
-fn main() {
-    if let Err(e) = commands::Handler::handle() {
-        eprintln!("Handler error: {e}");
+fn exit_code(error: &Error) -> u8 {
+    match error {
+        Error::Png(PngError::ChunkNotFound(_) | PngError::OccurrenceNotFound { .. } | PngError::NoTrailingData)
+        | Error::Signature(SignatureError::Unsigned)
+        | Error::Text(TextError::KeywordNotFound(_))
+        | Error::Stego(StegoError::NoPayload | StegoError::TagMismatch { .. }) => 3,
+        Error::Crypto(CryptoError::WrongPassphrase | CryptoError::NoMatchingIdentity) => 77,
+        Error::Chunk { .. } | Error::Png(_) | Error::Header(_) | Error::Pixel(_) | Error::Crypto(_) | Error::Compress(_) | Error::Fragment(_) | Error::Signature(_) | Error::Stego(_) | Error::Text(_) | Error::Utf8(_) => 65,
+        Error::Io(_) => 74,
+        _ => 1
     }
 }
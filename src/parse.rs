@@ -0,0 +1,319 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::chunk::{read_u32, Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::png::{validate_header, Png, PngError};
+use crate::{Error, Result, MAX_CHUNK_LEN};
+
+/// How [`Png::parse_with`] handles damaged input.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum ParseMode {
+    /// Fail on the first problem, like [`Png::try_from`].
+    #[default]
+    Strict,
+    /// Keep chunks whose CRC does not match and report them, but fail on structural damage.
+    Warn,
+    /// Also capture a truncated final chunk and skip garbage between chunks.
+    Recover
+}
+
+impl FromStr for ParseMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "strict" => Ok(ParseMode::Strict),
+            "warn" => Ok(ParseMode::Warn),
+            "recover" => Ok(ParseMode::Recover),
+            _ => Err(PngError::InvalidParseMode(s.to_string()).into())
+        }
+    }
+}
+
+impl Display for ParseMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseMode::Strict => write!(f, "strict"),
+            ParseMode::Warn => write!(f, "warn"),
+            ParseMode::Recover => write!(f, "recover")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub mode: ParseMode
+}
+
+impl ParseOptions {
+    pub fn strict() -> Self {
+        Self { mode: ParseMode::Strict }
+    }
+
+    pub fn warn() -> Self {
+        Self { mode: ParseMode::Warn }
+    }
+
+    pub fn recover() -> Self {
+        Self { mode: ParseMode::Recover }
+    }
+}
+
+/// A PNG parsed by [`Png::parse_with`] and the problems found on the way.
+pub struct Parsed {
+    pub png: Png,
+    /// Byte offset in the input of each chunk of `png`.
+    pub offsets: Vec<usize>,
+    pub diagnostics: Vec<Diagnostic>
+}
+
+impl Parsed {
+    /// Whether the chunk at `index` was kept despite a CRC mismatch.
+    pub fn crc_mismatch(&self, index: usize) -> bool {
+        self.diagnostics.iter().any(|d| matches!(d.kind, DiagnosticKind::CrcMismatch { index: i, .. } if i == index))
+    }
+}
+
+/// A problem found while parsing, at a byte offset from the start of the input.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub offset: usize,
+    pub kind: DiagnosticKind
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum DiagnosticKind {
+    /// The chunk at `index` was kept with the data as read.
+    CrcMismatch { index: usize, chunk_type: ChunkType, stored: u32, computed: u32 },
+    /// The input ends inside the chunk at `index`, which holds the bytes that were there.
+    Truncated { index: usize, chunk_type: ChunkType, declared: u32, available: usize },
+    /// Bytes that do not start a chunk were skipped up to the next plausible one.
+    Skipped { length: usize },
+    MissingIend
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Byte {}: ", self.offset)?;
+        match &self.kind {
+            DiagnosticKind::CrcMismatch { index, chunk_type, stored, computed } => {
+                write!(f, "chunk {index} ({chunk_type}) has CRC {stored:08x}, computed {computed:08x}")
+            }
+            DiagnosticKind::Truncated { index, chunk_type, declared, available } => {
+                write!(f, "chunk {index} ({chunk_type}) is truncated: {available} of {declared} data bytes")
+            }
+            DiagnosticKind::Skipped { length } => {
+                write!(f, "skipped {length} bytes that are not a chunk")
+            }
+            DiagnosticKind::MissingIend => {
+                write!(f, "no IEND chunk before the end of the input")
+            }
+        }
+    }
+}
+
+pub(crate) fn parse(bytes: &[u8], options: &ParseOptions) -> Result<Parsed> {
+    validate_header(bytes)?;
+    let mode = options.mode;
+
+    let mut chunks = Vec::new();
+    let mut offsets = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut cursor = Png::STANDARD_HEADER.len();
+    let mut iend = false;
+
+    while cursor < bytes.len() && !iend {
+        let rest = &bytes[cursor..];
+        let chunk = match ChunkRef::scan(rest) {
+            Ok(chunk) => chunk,
+            Err(e) if mode != ParseMode::Recover => return Err(e.at_offset(cursor)),
+            Err(_) => {
+                if let Some((chunk_type, declared)) = truncated_header(rest) {
+                    // A cut inside the CRC leaves the data whole, and the CRC bytes are not data
+                    let chunk = Chunk::new(chunk_type.clone(), rest[8..].iter().take(declared as usize).copied().collect());
+                    let kind = DiagnosticKind::Truncated { index: chunks.len(), chunk_type, declared, available: chunk.data().len() };
+                    diagnostics.push(Diagnostic { offset: cursor, kind });
+                    chunks.push(chunk);
+                    offsets.push(cursor);
+                    cursor = bytes.len();
+                    break;
+                }
+
+                let next = (cursor + 1..bytes.len()).find(|&p| plausible_chunk(&bytes[p..])).unwrap_or(bytes.len());
+                diagnostics.push(Diagnostic { offset: cursor, kind: DiagnosticKind::Skipped { length: next - cursor } });
+                cursor = next;
+                continue;
+            }
+        };
+
+        if let Err(e) = chunk.verify() {
+            if mode == ParseMode::Strict { return Err(e.at_offset(cursor)) }
+            let kind = DiagnosticKind::CrcMismatch {
+                index: chunks.len(),
+                chunk_type: chunk.chunk_type().clone(),
+                stored: chunk.stored_crc(),
+                computed: chunk.crc()
+            };
+            diagnostics.push(Diagnostic { offset: cursor, kind });
+        }
+
+        iend = chunk.chunk_type().to_string() == "IEND";
+        offsets.push(cursor);
+        cursor += 12 + chunk.data().len();
+        chunks.push(chunk.to_chunk());
+    }
+
+    if !iend && mode != ParseMode::Strict {
+        diagnostics.push(Diagnostic { offset: cursor, kind: DiagnosticKind::MissingIend });
+    }
+
+    let mut png = Png::from_chunks(chunks);
+    png.set_trailing_data(bytes[cursor..].to_vec());
+    Ok(Parsed { png, offsets, diagnostics })
+}
+
+/// The type and declared length of a chunk whose header is intact but which runs past the end of `rest`.
+fn truncated_header(rest: &[u8]) -> Option<(ChunkType, u32)> {
+    if rest.len() < 8 { return None }
+    let declared = read_u32(&rest[0..4]);
+    let chunk_type = ChunkType::try_from([rest[4], rest[5], rest[6], rest[7]]).ok().filter(ChunkType::is_valid)?;
+    (declared <= MAX_CHUNK_LEN && 12 + declared as usize > rest.len()).then_some((chunk_type, declared))
+}
+
+/// Whether `rest` starts with a valid chunk type and a length that fits in the input.
+fn plausible_chunk(rest: &[u8]) -> bool {
+    rest.len() >= 12
+        && ChunkType::try_from([rest[4], rest[5], rest[6], rest[7]]).is_ok_and(|t| t.is_valid())
+        && (read_u32(&rest[0..4]) as usize) <= rest.len() - 12
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::ChunkError;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    /// The signature and FrSt, miDl and IEND chunks, with the offset of each chunk.
+    fn testing_bytes() -> (Vec<u8>, Vec<usize>) {
+        let png = Png::from_chunks(vec![chunk("FrSt", "I am the first chunk"), chunk("miDl", "I am another chunk"), chunk("IEND", "")]);
+        let mut offsets = vec![8];
+        for c in png.chunks() {
+            offsets.push(offsets.last().unwrap() + 12 + c.data().len());
+        }
+        offsets.pop();
+        (png.as_bytes(), offsets)
+    }
+
+    fn types(parsed: &Parsed) -> Vec<String> {
+        parsed.png.chunks().iter().map(|c| c.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_clean_input() {
+        let (bytes, offsets) = testing_bytes();
+        for options in [ParseOptions::strict(), ParseOptions::warn(), ParseOptions::recover()] {
+            let parsed = Png::parse_with(&bytes, &options).unwrap();
+            assert!(parsed.diagnostics.is_empty());
+            assert_eq!(parsed.offsets, offsets);
+            assert_eq!(parsed.png.as_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_crc_mismatch() {
+        let (mut bytes, offsets) = testing_bytes();
+        let crc_at = offsets[2] - 1;
+        bytes[crc_at] ^= 0xFF;
+
+        match Png::parse_with(&bytes, &ParseOptions::strict()) {
+            Err(Error::Chunk { offset, source: ChunkError::CrcMismatch { .. } }) => assert_eq!(offset, offsets[1]),
+            _ => panic!("expected CRC mismatch")
+        }
+
+        let parsed = Png::parse_with(&bytes, &ParseOptions::warn()).unwrap();
+        assert_eq!(types(&parsed), ["FrSt", "miDl", "IEND"]);
+        assert!(parsed.crc_mismatch(1));
+        assert!(!parsed.crc_mismatch(0));
+        assert_eq!(parsed.diagnostics.len(), 1);
+        assert_eq!(parsed.diagnostics[0].offset, offsets[1]);
+        assert_eq!(parsed.png.chunks()[1].data(), b"I am another chunk");
+    }
+
+    #[test]
+    fn test_truncated_final_chunk() {
+        let (bytes, offsets) = testing_bytes();
+        let bytes = &bytes[..offsets[1] + 8 + 4];
+
+        assert!(Png::parse_with(bytes, &ParseOptions::strict()).is_err());
+        assert!(Png::parse_with(bytes, &ParseOptions::warn()).is_err());
+
+        let parsed = Png::parse_with(bytes, &ParseOptions::recover()).unwrap();
+        assert_eq!(types(&parsed), ["FrSt", "miDl"]);
+        assert_eq!(parsed.png.chunks()[1].data(), b"I am");
+        assert_eq!(parsed.diagnostics, [
+            Diagnostic {
+                offset: offsets[1],
+                kind: DiagnosticKind::Truncated { index: 1, chunk_type: ChunkType::from_str("miDl").unwrap(), declared: 18, available: 4 }
+            },
+            Diagnostic { offset: bytes.len(), kind: DiagnosticKind::MissingIend }
+        ]);
+    }
+
+    #[test]
+    fn test_truncated_inside_crc() {
+        let (bytes, offsets) = testing_bytes();
+        let bytes = &bytes[..offsets[1] + 8 + 18 + 2];
+
+        match Png::parse_with(bytes, &ParseOptions::strict()) {
+            Err(Error::Chunk { source: ChunkError::Truncated { declared, available }, .. }) => assert_eq!((declared, available), (18, 18)),
+            _ => panic!("expected truncated chunk")
+        }
+
+        let parsed = Png::parse_with(bytes, &ParseOptions::recover()).unwrap();
+        assert_eq!(parsed.png.chunks()[1].data(), b"I am another chunk");
+        assert_eq!(
+            parsed.diagnostics[0].kind,
+            DiagnosticKind::Truncated { index: 1, chunk_type: ChunkType::from_str("miDl").unwrap(), declared: 18, available: 18 }
+        );
+    }
+
+    #[test]
+    fn test_resync_after_garbage() {
+        let (bytes, offsets) = testing_bytes();
+        let garbage = [0xFFu8; 7];
+        let bytes = [&bytes[..offsets[1]], &garbage, &bytes[offsets[1]..], b"trailing"].concat();
+
+        assert!(Png::parse_with(&bytes, &ParseOptions::warn()).is_err());
+
+        let parsed = Png::parse_with(&bytes, &ParseOptions::recover()).unwrap();
+        assert_eq!(types(&parsed), ["FrSt", "miDl", "IEND"]);
+        assert_eq!(parsed.offsets[1], offsets[1] + garbage.len());
+        assert_eq!(parsed.diagnostics, [Diagnostic { offset: offsets[1], kind: DiagnosticKind::Skipped { length: garbage.len() } }]);
+        assert_eq!(parsed.png.trailing_data(), b"trailing");
+    }
+
+    #[test]
+    fn test_missing_iend() {
+        let (bytes, offsets) = testing_bytes();
+        let bytes = &bytes[..offsets[2]];
+
+        assert!(Png::parse_with(bytes, &ParseOptions::strict()).unwrap().diagnostics.is_empty());
+        let parsed = Png::parse_with(bytes, &ParseOptions::warn()).unwrap();
+        assert_eq!(parsed.diagnostics, [Diagnostic { offset: bytes.len(), kind: DiagnosticKind::MissingIend }]);
+        assert_eq!(parsed.diagnostics[0].to_string(), format!("Byte {}: no IEND chunk before the end of the input", bytes.len()));
+    }
+
+    #[test]
+    fn test_parse_mode_from_str() {
+        assert_eq!(ParseMode::from_str("recover").unwrap(), ParseMode::Recover);
+        assert_eq!(ParseMode::Warn.to_string(), "warn");
+        assert!(ParseMode::from_str("lenient").is_err());
+    }
+}
@@ -0,0 +1,614 @@
+use std::fmt::{Display, Formatter};
+
+use crate::compress;
+use crate::image_header::{ColorType, ImageHeader, InterlaceMethod};
+use crate::Result;
+
+/// Origin and spacing of the seven Adam7 passes: x, y, dx, dy.
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2)
+];
+
+/// One pixel's samples, widened to `u16`. Sub-byte samples keep their stored value, from 0 to
+/// 2^bit depth - 1, and indexed pixels hold a palette index.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Pixel {
+    Grayscale(u16),
+    Rgb(u16, u16, u16),
+    Indexed(u8),
+    GrayscaleAlpha(u16, u16),
+    Rgba(u16, u16, u16, u16)
+}
+
+impl Pixel {
+    pub fn color_type(&self) -> ColorType {
+        match self {
+            Pixel::Grayscale(_) => ColorType::Grayscale,
+            Pixel::Rgb(..) => ColorType::Rgb,
+            Pixel::Indexed(_) => ColorType::Indexed,
+            Pixel::GrayscaleAlpha(..) => ColorType::GrayscaleAlpha,
+            Pixel::Rgba(..) => ColorType::Rgba
+        }
+    }
+
+    fn from_samples(color_type: ColorType, s: &[u16]) -> Self {
+        match color_type {
+            ColorType::Grayscale => Pixel::Grayscale(s[0]),
+            ColorType::Rgb => Pixel::Rgb(s[0], s[1], s[2]),
+            ColorType::Indexed => Pixel::Indexed(s[0] as u8),
+            ColorType::GrayscaleAlpha => Pixel::GrayscaleAlpha(s[0], s[1]),
+            ColorType::Rgba => Pixel::Rgba(s[0], s[1], s[2], s[3])
+        }
+    }
+
+    fn samples(&self) -> Vec<u16> {
+        match *self {
+            Pixel::Grayscale(v) => vec![v],
+            Pixel::Rgb(r, g, b) => vec![r, g, b],
+            Pixel::Indexed(i) => vec![i as u16],
+            Pixel::GrayscaleAlpha(v, a) => vec![v, a],
+            Pixel::Rgba(r, g, b, a) => vec![r, g, b, a]
+        }
+    }
+}
+
+/// Every sample of an image in raster order, one element per sample: `U16` for 16-bit images
+/// and `U8` for the rest, sub-byte samples unpacked.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Samples {
+    U8(Vec<u8>),
+    U16(Vec<u16>)
+}
+
+impl Samples {
+    pub fn len(&self) -> usize {
+        match self {
+            Samples::U8(samples) => samples.len(),
+            Samples::U16(samples) => samples.len()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Unfiltered, deinterlaced image data: full-resolution rows packed as in the PNG
+/// (samples big-endian, sub-byte pixels most significant bits first) without filter type bytes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PixelBuffer {
+    header: ImageHeader,
+    data: Vec<u8>
+}
+
+impl PixelBuffer {
+    /// Wraps packed rows. `data` must hold exactly `height` rows of [`PixelBuffer::stride`] bytes.
+    pub fn new(header: ImageHeader, data: Vec<u8>) -> Result<Self> {
+        let expected = stride(&header, header.width() as usize) * header.height() as usize;
+        if data.len() != expected {
+            return Err(PixelError::BufferLength { expected, actual: data.len() }.into());
+        }
+        Ok(Self { header, data })
+    }
+
+    pub fn header(&self) -> &ImageHeader {
+        &self.header
+    }
+
+    pub fn width(&self) -> usize {
+        self.header.width() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.header.height() as usize
+    }
+
+    /// Bytes per row.
+    pub fn stride(&self) -> usize {
+        stride(&self.header, self.width())
+    }
+
+    pub fn row(&self, y: usize) -> &[u8] {
+        let stride = self.stride();
+        &self.data[y * stride..(y + 1) * stride]
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Packs `samples`, laid out as [`PixelBuffer::samples`] returns them.
+    pub fn from_samples(header: ImageHeader, samples: &Samples) -> Result<Self> {
+        let expected = header.width() as usize * header.height() as usize * header.color_type().channels() as usize;
+        if samples.len() != expected {
+            return Err(PixelError::SampleCount { expected, actual: samples.len() }.into());
+        }
+        let values: Vec<u16> = match (samples, header.bit_depth()) {
+            (Samples::U16(samples), 16) => samples.clone(),
+            (Samples::U8(samples), bit_depth) if bit_depth < 16 => samples.iter().map(|&v| v as u16).collect(),
+            _ => return Err(PixelError::SampleWidth(header.bit_depth()).into())
+        };
+
+        let data = vec![0; stride(&header, header.width() as usize) * header.height() as usize];
+        let mut pixels = Self { header, data };
+        let channels = pixels.header.color_type().channels() as usize;
+        for (i, &value) in values.iter().enumerate() {
+            let pixel = i / channels;
+            pixels.write_sample(pixel % pixels.width(), pixel / pixels.width(), i % channels, value)?;
+        }
+        Ok(pixels)
+    }
+
+    /// Unpacks every sample, row by row.
+    pub fn samples(&self) -> Samples {
+        let channels = self.header.color_type().channels() as usize;
+        let values = (0..self.height()).flat_map(|y| {
+            (0..self.width() * channels).map(move |i| self.read_sample(i / channels, y, i % channels))
+        });
+        if self.header.bit_depth() == 16 {
+            Samples::U16(values.collect())
+        } else {
+            Samples::U8(values.map(|v| v as u8).collect())
+        }
+    }
+
+    /// The pixel at column `x` of row `y`, or `None` if it is outside the image.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Pixel> {
+        if x >= self.width() || y >= self.height() { return None }
+        let channels = self.header.color_type().channels() as usize;
+        let samples: Vec<u16> = (0..channels).map(|c| self.read_sample(x, y, c)).collect();
+        Some(Pixel::from_samples(self.header.color_type(), &samples))
+    }
+
+    /// Overwrites the pixel at column `x` of row `y`. Its colour type must match the image's,
+    /// and every sample must fit in the bit depth.
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: Pixel) -> Result<()> {
+        if x >= self.width() || y >= self.height() {
+            return Err(PixelError::OutOfBounds { x, y }.into());
+        }
+        if pixel.color_type() != self.header.color_type() {
+            return Err(PixelError::ColorTypeMismatch { expected: self.header.color_type(), actual: pixel.color_type() }.into());
+        }
+        let samples = pixel.samples();
+        if let Some(&value) = samples.iter().find(|&&v| !fits(v, self.header.bit_depth())) {
+            return Err(PixelError::SampleOutOfRange { value, bit_depth: self.header.bit_depth() }.into());
+        }
+        for (c, value) in samples.into_iter().enumerate() {
+            self.write_sample(x, y, c, value)?;
+        }
+        Ok(())
+    }
+
+    fn read_sample(&self, x: usize, y: usize, channel: usize) -> u16 {
+        let bit_depth = self.header.bit_depth() as usize;
+        let row = self.row(y);
+        let bit = (x * self.header.color_type().channels() as usize + channel) * bit_depth;
+        match bit_depth {
+            16 => u16::from_be_bytes([row[bit / 8], row[bit / 8 + 1]]),
+            8 => row[bit / 8] as u16,
+            _ => ((row[bit / 8] >> (8 - bit_depth - bit % 8)) & ((1 << bit_depth) - 1)) as u16
+        }
+    }
+
+    fn write_sample(&mut self, x: usize, y: usize, channel: usize, value: u16) -> Result<()> {
+        let bit_depth = self.header.bit_depth();
+        if !fits(value, bit_depth) {
+            return Err(PixelError::SampleOutOfRange { value, bit_depth }.into());
+        }
+
+        let bit_depth = bit_depth as usize;
+        let bit = (x * self.header.color_type().channels() as usize + channel) * bit_depth;
+        let index = y * self.stride() + bit / 8;
+        match bit_depth {
+            16 => self.data[index..index + 2].copy_from_slice(&value.to_be_bytes()),
+            8 => self.data[index] = value as u8,
+            _ => {
+                let shift = 8 - bit_depth - bit % 8;
+                let mask = ((1u8 << bit_depth) - 1) << shift;
+                self.data[index] = (self.data[index] & !mask) | ((value as u8) << shift);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn fits(value: u16, bit_depth: u8) -> bool {
+    bit_depth >= 16 || value < 1 << bit_depth
+}
+
+/// Inflates, unfilters and deinterlaces the concatenated IDAT data of an image.
+pub(crate) fn decode(header: &ImageHeader, compressed: &[u8]) -> Result<PixelBuffer> {
+    let expected = filtered_len(header).ok_or(PixelError::TooLarge)?;
+    let filtered = compress::inflate(compressed, expected as u64)?;
+    if filtered.len() != expected {
+        return Err(PixelError::DataLength { expected, actual: filtered.len() }.into());
+    }
+
+    let width = header.width() as usize;
+    let height = header.height() as usize;
+    let filter_bpp = filter_bpp(header);
+
+    match header.interlace_method() {
+        InterlaceMethod::None => {
+            let data = unfilter(&filtered, stride(header, width), height, filter_bpp)?;
+            PixelBuffer::new(header.clone(), data)
+        }
+        InterlaceMethod::Adam7 => {
+            let full_stride = stride(header, width);
+            let mut data = vec![0; full_stride * height];
+            let mut offset = 0;
+            for (pass_width, pass_height, pass) in passes(width, height) {
+                if pass_width == 0 || pass_height == 0 { continue }
+                let pass_stride = stride(header, pass_width);
+                let pass_len = (pass_stride + 1) * pass_height;
+                let reduced = unfilter(&filtered[offset..offset + pass_len], pass_stride, pass_height, filter_bpp)?;
+                offset += pass_len;
+
+                for_each_pass_pixel(pass, pass_width, pass_height, |x, y, px, py| {
+                    copy_pixel(&reduced[py * pass_stride..], px, &mut data[y * full_stride..], x, header.bits_per_pixel());
+                });
+            }
+            PixelBuffer::new(header.clone(), data)
+        }
+    }
+}
+
+/// Inflates and unfilters only the first `rows` rows of a non-interlaced image, ignoring the
+/// rest of the data, so the memory used is bounded by `rows` rather than the declared height.
+pub(crate) fn decode_rows(header: &ImageHeader, compressed: &[u8], rows: u32) -> Result<PixelBuffer> {
+    let top = ImageHeader::new(header.width(), rows, header.bit_depth(), header.color_type(), InterlaceMethod::None)?;
+    let expected = filtered_len(&top).ok_or(PixelError::TooLarge)?;
+    let mut filtered = compress::inflate(compressed, expected as u64)?;
+    if filtered.len() < expected {
+        return Err(PixelError::DataLength { expected, actual: filtered.len() }.into());
+    }
+    filtered.truncate(expected);
+
+    let data = unfilter(&filtered, stride(&top, top.width() as usize), rows as usize, filter_bpp(&top))?;
+    PixelBuffer::new(top, data)
+}
+
+/// Interlaces (if the header asks for it), filters and deflates a pixel buffer into IDAT data.
+pub(crate) fn encode(pixels: &PixelBuffer) -> Result<Vec<u8>> {
+    let header = pixels.header();
+    let bits_per_pixel = header.bits_per_pixel();
+    let filter_bpp = filter_bpp(header);
+    let adaptive = header.bit_depth() >= 8 && header.color_type() != ColorType::Indexed;
+
+    let filtered = match header.interlace_method() {
+        InterlaceMethod::None => filter(pixels.as_bytes(), pixels.stride(), pixels.height(), filter_bpp, adaptive),
+        InterlaceMethod::Adam7 => {
+            let mut filtered = Vec::with_capacity(filtered_len(header).unwrap_or_default());
+            for (pass_width, pass_height, pass) in passes(pixels.width(), pixels.height()) {
+                if pass_width == 0 || pass_height == 0 { continue }
+                let pass_stride = stride(header, pass_width);
+                let mut reduced = vec![0; pass_stride * pass_height];
+
+                for_each_pass_pixel(pass, pass_width, pass_height, |x, y, px, py| {
+                    copy_pixel(pixels.row(y), x, &mut reduced[py * pass_stride..], px, bits_per_pixel);
+                });
+                filtered.extend(filter(&reduced, pass_stride, pass_height, filter_bpp, adaptive));
+            }
+            filtered
+        }
+    };
+    compress::deflate(&filtered)
+}
+
+/// Width and height of each Adam7 pass, with the pass origin and spacing.
+fn passes(width: usize, height: usize) -> impl Iterator<Item = (usize, usize, (usize, usize, usize, usize))> {
+    ADAM7.into_iter().map(move |pass @ (x0, y0, dx, dy)| {
+        let pass_width = if width > x0 { (width - x0).div_ceil(dx) } else { 0 };
+        let pass_height = if height > y0 { (height - y0).div_ceil(dy) } else { 0 };
+        (pass_width, pass_height, pass)
+    })
+}
+
+fn for_each_pass_pixel<F>(pass: (usize, usize, usize, usize), pass_width: usize, pass_height: usize, mut f: F)
+where
+    F: FnMut(usize, usize, usize, usize)
+{
+    let (x0, y0, dx, dy) = pass;
+    for py in 0..pass_height {
+        for px in 0..pass_width {
+            f(x0 + px * dx, y0 + py * dy, px, py);
+        }
+    }
+}
+
+/// Copies pixel `from_x` of row `from` to pixel `to_x` of row `to`.
+fn copy_pixel(from: &[u8], from_x: usize, to: &mut [u8], to_x: usize, bits_per_pixel: usize) {
+    if bits_per_pixel >= 8 {
+        let bytes = bits_per_pixel / 8;
+        to[to_x * bytes..(to_x + 1) * bytes].copy_from_slice(&from[from_x * bytes..(from_x + 1) * bytes]);
+        return;
+    }
+
+    let mask = (1u8 << bits_per_pixel) - 1;
+    let from_shift = 8 - bits_per_pixel - (from_x * bits_per_pixel) % 8;
+    let to_shift = 8 - bits_per_pixel - (to_x * bits_per_pixel) % 8;
+    let value = (from[from_x * bits_per_pixel / 8] >> from_shift) & mask;
+
+    let byte = &mut to[to_x * bits_per_pixel / 8];
+    *byte = (*byte & !(mask << to_shift)) | (value << to_shift);
+}
+
+fn stride(header: &ImageHeader, width: usize) -> usize {
+    (width * header.bits_per_pixel()).div_ceil(8)
+}
+
+/// Length of the filtered, uncompressed image data, including a filter byte per row.
+///
+/// `None` if it does not fit in memory, which the header dimensions allow.
+pub(crate) fn filtered_len(header: &ImageHeader) -> Option<usize> {
+    let width = header.width() as usize;
+    let height = header.height() as usize;
+    match header.interlace_method() {
+        InterlaceMethod::None => (stride(header, width) + 1).checked_mul(height),
+        InterlaceMethod::Adam7 => passes(width, height)
+            .filter(|(w, h, _)| *w > 0 && *h > 0)
+            .try_fold(0usize, |len, (w, h, _)| len.checked_add((stride(header, w) + 1).checked_mul(h)?))
+    }
+}
+
+/// Distance in bytes to the corresponding byte of the previous pixel, at least 1.
+fn filter_bpp(header: &ImageHeader) -> usize {
+    header.bits_per_pixel().div_ceil(8)
+}
+
+fn unfilter(filtered: &[u8], stride: usize, height: usize, bpp: usize) -> Result<Vec<u8>> {
+    let mut data = vec![0; stride * height];
+    for y in 0..height {
+        let filter_type = filtered[y * (stride + 1)];
+        let line = &filtered[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (previous, current) = data.split_at_mut(y * stride);
+        let previous = if y == 0 { None } else { Some(&previous[(y - 1) * stride..]) };
+        let current = &mut current[..stride];
+
+        for i in 0..stride {
+            let a = if i >= bpp { current[i - bpp] } else { 0 };
+            let b = previous.map_or(0, |p| p[i]);
+            let c = if i >= bpp { previous.map_or(0, |p| p[i - bpp]) } else { 0 };
+            current[i] = line[i].wrapping_add(match filter_type {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                other => return Err(PixelError::InvalidFilterType { filter_type: other, row: y }.into())
+            });
+        }
+    }
+    Ok(data)
+}
+
+/// Filters every row, picking the filter with the smallest sum of absolute differences if
+/// `adaptive`, as the specification suggests, or no filter otherwise.
+fn filter(data: &[u8], stride: usize, height: usize, bpp: usize, adaptive: bool) -> Vec<u8> {
+    let mut filtered = Vec::with_capacity((stride + 1) * height);
+    let mut candidate = vec![0; stride];
+    let mut best = vec![0; stride];
+
+    for y in 0..height {
+        let current = &data[y * stride..(y + 1) * stride];
+        let previous = if y == 0 { None } else { Some(&data[(y - 1) * stride..y * stride]) };
+
+        let filter_types: &[u8] = if adaptive { &[0, 1, 2, 3, 4] } else { &[0] };
+        let mut best_type = 0;
+        let mut best_score = u64::MAX;
+        for &filter_type in filter_types {
+            for i in 0..stride {
+                let a = if i >= bpp { current[i - bpp] } else { 0 };
+                let b = previous.map_or(0, |p| p[i]);
+                let c = if i >= bpp { previous.map_or(0, |p| p[i - bpp]) } else { 0 };
+                candidate[i] = current[i].wrapping_sub(match filter_type {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c)
+                });
+            }
+
+            let score = candidate.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum();
+            if score < best_score {
+                best_score = score;
+                best_type = filter_type;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+
+        filtered.push(best_type);
+        filtered.extend_from_slice(&best);
+    }
+    filtered
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PixelError {
+    /// There are no IDAT chunks.
+    MissingImageData,
+    /// The inflated image data does not match the size the header implies.
+    DataLength { expected: usize, actual: usize },
+    InvalidFilterType { filter_type: u8, row: usize },
+    BufferLength { expected: usize, actual: usize },
+    /// The image dimensions are too large to decode in memory.
+    TooLarge,
+    SampleCount { expected: usize, actual: usize },
+    /// 16-bit images take `u16` samples and other bit depths `u8`. Holds the bit depth.
+    SampleWidth(u8),
+    SampleOutOfRange { value: u16, bit_depth: u8 },
+    ColorTypeMismatch { expected: ColorType, actual: ColorType },
+    OutOfBounds { x: usize, y: usize }
+}
+
+impl Display for PixelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PixelError::MissingImageData => {
+                write!(f, "The image has no IDAT chunks")
+            }
+            PixelError::DataLength { expected, actual } => {
+                write!(f, "Image data is {actual} bytes after decompression. Expected: {expected}")
+            }
+            PixelError::InvalidFilterType { filter_type, row } => {
+                write!(f, "Invalid filter type {filter_type} in row {row}")
+            }
+            PixelError::BufferLength { expected, actual } => {
+                write!(f, "Pixel buffer is {actual} bytes. Expected: {expected}")
+            }
+            PixelError::TooLarge => {
+                write!(f, "The image is too large to decode in memory")
+            }
+            PixelError::SampleCount { expected, actual } => {
+                write!(f, "Got {actual} samples. Expected: {expected}")
+            }
+            PixelError::SampleWidth(bit_depth) => {
+                let width = if *bit_depth == 16 { "16" } else { "8" };
+                write!(f, "A {bit_depth}-bit image takes {width}-bit samples")
+            }
+            PixelError::SampleOutOfRange { value, bit_depth } => {
+                write!(f, "Sample value {value} does not fit in {bit_depth} bits")
+            }
+            PixelError::ColorTypeMismatch { expected, actual } => {
+                write!(f, "Cannot store a {actual} pixel in a {expected} image")
+            }
+            PixelError::OutOfBounds { x, y } => {
+                write!(f, "Pixel ({x}, {y}) is outside the image")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PixelError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(header: &ImageHeader) -> PixelBuffer {
+        let stride = stride(header, header.width() as usize);
+        let mut data: Vec<u8> = (0..stride * header.height() as usize).map(|i| (i * 7 + i / 13) as u8).collect();
+
+        // Bits past the last pixel of a row are padding that interlacing does not keep
+        let padding = stride * 8 - header.width() as usize * header.bits_per_pixel();
+        for row in data.chunks_mut(stride) {
+            row[stride - 1] &= 0xFF << padding;
+        }
+        PixelBuffer::new(header.clone(), data).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_every_format() {
+        for color_type in [ColorType::Grayscale, ColorType::Rgb, ColorType::Indexed, ColorType::GrayscaleAlpha, ColorType::Rgba] {
+            for &bit_depth in color_type.allowed_bit_depths() {
+                for interlace in [InterlaceMethod::None, InterlaceMethod::Adam7] {
+                    let header = ImageHeader::new(13, 11, bit_depth, color_type, interlace).unwrap();
+                    let pixels = gradient(&header);
+                    let decoded = decode(&header, &encode(&pixels).unwrap()).unwrap();
+                    assert_eq!(decoded, pixels, "{color_type} at {bit_depth} bits, {interlace:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_samples_round_trip_every_format() {
+        for color_type in [ColorType::Grayscale, ColorType::Rgb, ColorType::Indexed, ColorType::GrayscaleAlpha, ColorType::Rgba] {
+            for &bit_depth in color_type.allowed_bit_depths() {
+                let header = ImageHeader::new(13, 11, bit_depth, color_type, InterlaceMethod::None).unwrap();
+                let pixels = gradient(&header);
+                let samples = pixels.samples();
+                assert_eq!(samples.len(), 13 * 11 * color_type.channels() as usize);
+                assert_eq!(PixelBuffer::from_samples(header, &samples).unwrap(), pixels, "{color_type} at {bit_depth} bits");
+            }
+        }
+    }
+
+    #[test]
+    fn test_pixel_access() {
+        let header = ImageHeader::new(3, 2, 16, ColorType::Rgba, InterlaceMethod::None).unwrap();
+        let mut pixels = PixelBuffer::new(header, vec![0; 3 * 2 * 8]).unwrap();
+        pixels.set_pixel(2, 1, Pixel::Rgba(0x1234, 1, 2, 0xFFFF)).unwrap();
+        assert_eq!(pixels.pixel(2, 1), Some(Pixel::Rgba(0x1234, 1, 2, 0xFFFF)));
+        assert_eq!(&pixels.row(1)[16..18], &[0x12, 0x34]);
+        assert_eq!(pixels.pixel(3, 0), None);
+        assert!(pixels.set_pixel(0, 0, Pixel::Rgb(0, 0, 0)).is_err());
+
+        // Sub-byte pixels are packed most significant bits first
+        let header = ImageHeader::new(4, 1, 2, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+        let mut pixels = PixelBuffer::new(header, vec![0b00_01_10_11]).unwrap();
+        assert_eq!(pixels.pixel(1, 0), Some(Pixel::Grayscale(1)));
+        assert_eq!(pixels.samples(), Samples::U8(vec![0, 1, 2, 3]));
+        pixels.set_pixel(0, 0, Pixel::Grayscale(3)).unwrap();
+        assert_eq!(pixels.as_bytes(), &[0b11_01_10_11]);
+        assert!(matches!(
+            pixels.set_pixel(0, 0, Pixel::Grayscale(4)),
+            Err(crate::Error::Pixel(PixelError::SampleOutOfRange { value: 4, bit_depth: 2 }))
+        ));
+        assert!(PixelBuffer::from_samples(pixels.header().clone(), &Samples::U16(vec![0; 4])).is_err());
+    }
+
+    #[test]
+    fn test_unfilter_all_filter_types() {
+        // Two 3-byte rows of one-byte pixels, each row using a different filter
+        let rows = [10, 20, 30, 40, 50, 60];
+        for filter_type in 0..5 {
+            let mut filtered = Vec::new();
+            for y in 0..2 {
+                filtered.push(filter_type);
+                for i in 0..3 {
+                    let a = if i >= 1 { rows[y * 3 + i - 1] } else { 0 };
+                    let b = if y > 0 { rows[i] } else { 0 };
+                    let c = if y > 0 && i >= 1 { rows[i - 1] } else { 0 };
+                    let predictor = match filter_type {
+                        0 => 0,
+                        1 => a,
+                        2 => b,
+                        3 => ((a as u16 + b as u16) / 2) as u8,
+                        _ => paeth(a, b, c)
+                    };
+                    filtered.push(rows[y * 3 + i].wrapping_sub(predictor));
+                }
+            }
+            assert_eq!(unfilter(&filtered, 3, 2, 1).unwrap(), rows, "filter type {filter_type}");
+        }
+        assert!(unfilter(&[5, 0, 0, 0], 3, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_decode_checks_length() {
+        let header = ImageHeader::new(4, 4, 8, ColorType::Grayscale, InterlaceMethod::None).unwrap();
+        let short = compress::deflate(&[0; 10]).unwrap();
+        assert!(matches!(decode(&header, &short), Err(crate::Error::Pixel(PixelError::DataLength { expected: 20, actual: 10 }))));
+    }
+}
@@ -1,39 +1,214 @@
 use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
 use std::path::Path;
-use crate::chunk::Chunk;
+use std::str::FromStr;
+use crate::chunk::{Chunk, ChunkRef};
+use crate::chunk_type::ChunkType;
+use crate::file::{PngFile, MMAP_THRESHOLD};
+use crate::image_header::{HeaderError, ImageHeader};
+use crate::pixels::{self, PixelBuffer, PixelError};
+use crate::validate::{self, Violation};
+use crate::parse::{self, ParseOptions, Parsed};
+use crate::stream::{ChunkReader, ChunkWriter};
 use crate::{Result, Error};
 
+/// Where [`Png::insert_chunk`] places a new chunk.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ChunkPosition {
+    #[default]
+    /// Immediately before `IEND`, or at the end if there is none.
+    BeforeIend,
+    AfterIhdr,
+    BeforeFirstIdat,
+    AfterLastIdat,
+    /// Absolute index into [`Png::chunks`].
+    Index(usize)
+}
+
+impl FromStr for ChunkPosition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "before-iend" => Ok(ChunkPosition::BeforeIend),
+            "after-ihdr" => Ok(ChunkPosition::AfterIhdr),
+            "before-idat" => Ok(ChunkPosition::BeforeFirstIdat),
+            "after-idat" => Ok(ChunkPosition::AfterLastIdat),
+            _ => s
+                .parse::<usize>()
+                .map(ChunkPosition::Index)
+                .map_err(|_| PngError::InvalidPosition(s.to_string()).into())
+        }
+    }
+}
+
+/// Which of the chunks sharing a type to act on.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Occurrence {
+    #[default]
+    First,
+    /// The chunk at this zero-based position among chunks of the same type.
+    Nth(usize),
+    All
+}
+
+impl Occurrence {
+    /// Whether the `n`th chunk of a type is selected.
+    pub fn matches(&self, n: usize) -> bool {
+        match self {
+            Occurrence::First => n == 0,
+            Occurrence::Nth(index) => n == *index,
+            Occurrence::All => true
+        }
+    }
+
+    /// The error for a selection that matched nothing among `count` chunks of `chunk_type`.
+    pub(crate) fn not_found(&self, chunk_type: &str, count: usize) -> Error {
+        match self {
+            Occurrence::Nth(index) if count > 0 => {
+                PngError::OccurrenceNotFound { chunk_type: chunk_type.to_string(), index: *index, count }.into()
+            }
+            _ => PngError::ChunkNotFound(chunk_type.to_string()).into()
+        }
+    }
+}
+
+/// A PNG file as an ordered list of chunks, followed by any bytes after IEND.
 pub struct Png {
-    chunks: Vec<Chunk>
+    chunks: Vec<Chunk>,
+    trailing: Vec<u8>
 }
 
 impl Png {
+    /// The eight-byte signature every PNG file starts with.
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
+    /// Largest IDAT chunk written by [`Png::set_pixels`].
+    pub const IDAT_CHUNK_LEN: usize = 1 << 20;
+
     pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
         Self {
-            chunks
+            chunks,
+            trailing: Vec::new()
         }
     }
 
+    /// Reads and parses a PNG file, memory-mapping it if it is at least [`MMAP_THRESHOLD`] bytes long.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let bytes = std::fs::read(path)?;
-        Png::try_from(bytes.as_slice())
+        Png::from_file_with_threshold(path, MMAP_THRESHOLD)
     }
 
+    /// Reads and parses a PNG file, memory-mapping it if it is at least `threshold` bytes long.
+    ///
+    /// Smaller files are streamed with [`Png::from_reader`]. See [`PngFile`] to borrow chunks from the
+    /// mapping instead of copying them.
+    pub fn from_file_with_threshold<P: AsRef<Path>>(path: P, threshold: u64) -> Result<Self> {
+        let file = File::open(&path)?;
+        if file.metadata()?.len() < threshold {
+            return Png::from_reader(BufReader::new(file));
+        }
+        Ok(PngFile::open_with_threshold(path, threshold)?.png()?.to_png())
+    }
+
+    /// Parses a PNG from a reader without buffering the whole input first.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut reader = ChunkReader::new(reader)?;
+        let chunks = reader.by_ref().collect::<Result<Vec<Chunk>>>()?;
+        let mut trailing = Vec::new();
+        reader.into_inner().read_to_end(&mut trailing)?;
+        Ok(Self { chunks, trailing })
+    }
+
+    /// Parses a PNG, tolerating the damage `options` allows and reporting it as diagnostics.
+    pub fn parse_with(bytes: &[u8], options: &ParseOptions) -> Result<Parsed> {
+        parse::parse(bytes, options)
+    }
+
+    /// Writes the signature and every chunk to `writer`, without the trailing data.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<W> {
+        let mut writer = ChunkWriter::new(writer)?;
+        for chunk in &self.chunks {
+            writer.write_chunk(chunk)?;
+        }
+        writer.into_inner()
+    }
+
+    /// Pushes a chunk onto the end of the file, after `IEND` if present.
+    /// Prefer [`Png::insert_chunk`] for chunks that should be read by decoders.
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk)
     }
 
+    /// Inserts a chunk at `position`.
+    ///
+    /// Fails if the anchor chunk for `position` is missing or the index is past `IEND` or out of bounds.
+    pub fn insert_chunk(&mut self, chunk: Chunk, position: ChunkPosition) -> Result<()> {
+        let index = match position {
+            ChunkPosition::BeforeIend => self
+                .position_of("IEND")
+                .unwrap_or(self.chunks.len()),
+            ChunkPosition::AfterIhdr => self
+                .position_of("IHDR")
+                .map(|pos| pos + 1)
+                .ok_or(PngError::AnchorNotFound("IHDR"))?,
+            ChunkPosition::BeforeFirstIdat => self
+                .position_of("IDAT")
+                .ok_or(PngError::AnchorNotFound("IDAT"))?,
+            ChunkPosition::AfterLastIdat => self.chunks
+                .iter()
+                .rposition(|c| c.chunk_type().to_string() == "IDAT")
+                .map(|pos| pos + 1)
+                .ok_or(PngError::AnchorNotFound("IDAT"))?,
+            ChunkPosition::Index(index) => {
+                // Nothing after IEND is read back as a chunk
+                let max = self.position_of("IEND").unwrap_or(self.chunks.len());
+                if index > max {
+                    return Err(PngError::IndexOutOfBounds(index, max).into());
+                }
+                index
+            }
+        };
+
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// Removes and returns the first chunk of the given type.
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
-        self.chunks
-            .iter()
-            .position(|c| c.chunk_type().to_string() == chunk_type)
-            .map_or_else(
-                || Err(Box::new(PngError::ChunkNotFound) as Box<dyn std::error::Error>),
-                |pos| Ok(self.chunks.remove(pos)))
+        self.position_of(chunk_type)
+            .map(|pos| self.chunks.remove(pos))
+            .ok_or_else(|| PngError::ChunkNotFound(chunk_type.to_string()).into())
     }
 
+    /// Removes and returns the chunks of the given type selected by `occurrence`, in file order.
+    pub fn remove_chunks(&mut self, chunk_type: &str, occurrence: Occurrence) -> Result<Vec<Chunk>> {
+        let mut count = 0;
+        let mut removed = Vec::new();
+        let mut kept = Vec::with_capacity(self.chunks.len());
+
+        for chunk in self.chunks.drain(..) {
+            if chunk.chunk_type().to_string() == chunk_type {
+                count += 1;
+                if occurrence.matches(count - 1) {
+                    removed.push(chunk);
+                    continue;
+                }
+            }
+            kept.push(chunk);
+        }
+        self.chunks = kept;
+
+        if removed.is_empty() { return Err(occurrence.not_found(chunk_type, count)) }
+        Ok(removed)
+    }
+
+    /// Keeps only the chunks for which `keep` returns true.
+    pub fn retain_chunks<F: FnMut(&Chunk) -> bool>(&mut self, keep: F) {
+        self.chunks.retain(keep)
+    }
+
+    /// The PNG signature.
     pub fn header(&self) -> &[u8; 8] {
         &Self::STANDARD_HEADER
     }
@@ -42,67 +217,262 @@ impl Png {
         &self.chunks
     }
 
+    fn position_of(&self, chunk_type: &str) -> Option<usize> {
+        self.chunks
+            .iter()
+            .position(|c| c.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Parses the IHDR chunk, which must be the first chunk.
+    pub fn image_header(&self) -> Result<ImageHeader> {
+        match self.chunks.first() {
+            Some(chunk) if chunk.chunk_type().to_string() == "IHDR" => ImageHeader::try_from(chunk.data()),
+            _ => Err(HeaderError::MissingIhdr.into())
+        }
+    }
+
+    /// Decodes the image: concatenates the IDAT chunks, inflates them and reverses filtering
+    /// and interlacing.
+    pub fn pixels(&self) -> Result<PixelBuffer> {
+        decode_image_data(&self.image_header()?, self.chunks_by_type("IDAT").map(|(_, c)| c.data()))
+    }
+
+    /// Encodes `pixels` and replaces the IDAT chunks with the result.
+    ///
+    /// The IHDR chunk is replaced with the header of `pixels`, so the interlace method may change.
+    pub fn set_pixels(&mut self, pixels: &PixelBuffer) -> Result<()> {
+        let compressed = pixels::encode(pixels)?;
+        let position = self.position_of("IDAT").ok_or(PixelError::MissingImageData)?;
+        self.retain_chunks(|c| c.chunk_type().to_string() != "IDAT");
+
+        let idat = ChunkType::from_str("IDAT").unwrap();
+        for (i, data) in compressed.chunks(Self::IDAT_CHUNK_LEN).enumerate() {
+            self.chunks.insert(position + i, Chunk::new(idat.clone(), data.to_vec()));
+        }
+        if let Some(ihdr) = self.chunks.iter_mut().find(|c| c.chunk_type().to_string() == "IHDR") {
+            *ihdr = pixels.header().to_chunk();
+        }
+        Ok(())
+    }
+
+    /// Checks chunk ordering and multiplicity against the PNG specification.
+    ///
+    /// Returns every violation found; an empty list means the structure is valid.
+    pub fn validate(&self) -> Vec<Violation> {
+        validate::validate(self)
+    }
+
+    /// The first chunk of the given type.
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         self.chunks
             .iter()
             .find(|c| c.chunk_type().to_string() == chunk_type)
     }
 
+    /// Every chunk of the given type with its index, in file order.
+    pub fn chunks_by_type<'s>(&'s self, chunk_type: &'s str) -> impl Iterator<Item = (usize, &'s Chunk)> + 's {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(move |(_, c)| c.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Bytes after IEND, which decoders ignore. Parsing stops at IEND and keeps them here.
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing
+    }
+
+    pub fn set_trailing_data(&mut self, data: Vec<u8>) {
+        self.trailing = data;
+    }
+
+    /// Serialized file: signature followed by every chunk. Trailing data is left out.
     pub fn as_bytes(&self) -> Vec<u8> {
         Self::STANDARD_HEADER
             .into_iter()
             .chain(self.chunks.iter().flat_map(|c| c.as_bytes()))
             .collect()
     }
+
+    /// Like [`Png::as_bytes`], followed by the trailing data.
+    pub fn as_bytes_with_trailing_data(&self) -> Vec<u8> {
+        let mut bytes = self.as_bytes();
+        bytes.extend_from_slice(&self.trailing);
+        bytes
+    }
 }
 
 impl TryFrom<&[u8]> for Png {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
+        PngRef::try_from(value).map(|png| png.to_png())
+    }
+}
+
+/// A PNG whose chunks borrow from an input buffer, for inspecting files without copying chunk data.
+pub struct PngRef<'a> {
+    chunks: Vec<ChunkRef<'a>>,
+    trailing: &'a [u8]
+}
+
+impl<'a> PngRef<'a> {
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks
+    }
+
+    /// Bytes after IEND. See [`Png::trailing_data`].
+    pub fn trailing_data(&self) -> &'a [u8] {
+        self.trailing
+    }
+
+    /// Byte offset of the trailing data from the start of the file.
+    pub fn trailing_offset(&self) -> usize {
+        self.offset_of(self.chunks.len())
+    }
+
+    /// The first chunk of the given type.
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&ChunkRef<'a>> {
+        self.chunks
+            .iter()
+            .find(|c| c.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Every chunk of the given type with its index, in file order.
+    pub fn chunks_by_type<'s>(&'s self, chunk_type: &'s str) -> impl Iterator<Item = (usize, &'s ChunkRef<'a>)> + 's {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(move |(_, c)| c.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Byte offset of the chunk at `index` from the start of the file.
+    pub fn offset_of(&self, index: usize) -> usize {
+        Png::STANDARD_HEADER.len() + self.chunks[..index].iter().map(|c| 12 + c.length() as usize).sum::<usize>()
+    }
+
+    /// Parses the IHDR chunk, which must be the first chunk.
+    pub fn image_header(&self) -> Result<ImageHeader> {
+        match self.chunks.first() {
+            Some(chunk) if chunk.chunk_type().to_string() == "IHDR" => ImageHeader::try_from(chunk.data()),
+            _ => Err(HeaderError::MissingIhdr.into())
+        }
+    }
+
+    /// Decodes the image without copying the chunks. See [`Png::pixels`].
+    pub fn pixels(&self) -> Result<PixelBuffer> {
+        decode_image_data(&self.image_header()?, self.chunks_by_type("IDAT").map(|(_, c)| c.data()))
+    }
+
+    /// Walks the chunk headers in `value` without checking CRCs. See [`ChunkRef::scan`].
+    pub fn scan(value: &'a [u8]) -> Result<Self> {
+        Self::parse(value, ChunkRef::scan)
+    }
+
+    fn parse<F>(value: &'a [u8], parse_chunk: F) -> Result<Self>
+    where
+        F: Fn(&'a [u8]) -> Result<ChunkRef<'a>>
+    {
         validate_header(value)?;
 
-        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut chunks: Vec<ChunkRef<'a>> = Vec::new();
         let mut cursor = 8;
         while cursor < value.len() {
-            let chunk = Chunk::try_from(&value[cursor..])?;
+            let chunk = parse_chunk(&value[cursor..]).map_err(|e| e.at_offset(cursor))?;
             cursor += 12 + chunk.length() as usize;
+            let iend = chunk.chunk_type().to_string() == "IEND";
             chunks.push(chunk);
+            if iend { break }
         }
 
         Ok(Self {
-            chunks
+            chunks,
+            trailing: &value[cursor..]
         })
     }
+
+    /// Copies every chunk and the trailing data into an owned [`Png`].
+    pub fn to_png(&self) -> Png {
+        Png {
+            chunks: self.chunks.iter().map(|c| c.to_chunk()).collect(),
+            trailing: self.trailing.to_vec()
+        }
+    }
 }
 
-fn validate_header(bytes: &[u8]) -> Result<()> {
-    if bytes.len() < 8 { return Err(Box::new(PngError::InvalidLength)); }
-    if bytes[..8] != Png::STANDARD_HEADER { return Err(Box::new(PngError::IncorrectPngHeader)); }
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = Error;
+
+    fn try_from(value: &'a [u8]) -> std::result::Result<Self, Self::Error> {
+        Self::parse(value, ChunkRef::try_from)
+    }
+}
+
+pub(crate) fn validate_header(bytes: &[u8]) -> Result<()> {
+    if bytes.len() < 8 { return Err(PngError::InvalidLength.into()); }
+    if bytes[..8] != Png::STANDARD_HEADER { return Err(PngError::IncorrectPngHeader.into()); }
     Ok(())
 }
 
+/// Concatenates the data of the IDAT chunks and decodes it.
+fn decode_image_data<'d, I>(header: &ImageHeader, idat: I) -> Result<PixelBuffer>
+where
+    I: Iterator<Item = &'d [u8]>
+{
+    let mut compressed = Vec::new();
+    for data in idat {
+        compressed.extend_from_slice(data);
+    }
+    if compressed.is_empty() { return Err(PixelError::MissingImageData.into()) }
+    pixels::decode(header, &compressed)
+}
+
 impl Display for Png {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         for x in self.chunks.iter() {
             writeln!(f, "{}", x)?;
         }
+        if !self.trailing.is_empty() {
+            writeln!(f, "Trailing data: {} bytes", self.trailing.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for PngRef<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for x in self.chunks.iter() {
+            writeln!(f, "{}", x)?;
+        }
+        if !self.trailing.is_empty() {
+            writeln!(f, "Trailing data: {} bytes at byte {}", self.trailing.len(), self.trailing_offset())?;
+        }
         Ok(())
     }
 }
 
 #[derive(Debug)]
-enum PngError {
-    ChunkNotFound,
+#[non_exhaustive]
+pub enum PngError {
+    ChunkNotFound(String),
     InvalidLength,
-    IncorrectPngHeader
+    IncorrectPngHeader,
+    InvalidPosition(String),
+    AnchorNotFound(&'static str),
+    IndexOutOfBounds(usize, usize),
+    /// There are only `count` chunks of `chunk_type`.
+    OccurrenceNotFound { chunk_type: String, index: usize, count: usize },
+    /// [`Png::validate`] found this many violations.
+    ValidationFailed(usize),
+    NoTrailingData,
+    InvalidParseMode(String)
 }
 
 impl Display for PngError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            PngError::ChunkNotFound => {
-                write!(f, "Cannot remove chunk that does not exist")
+            PngError::ChunkNotFound(chunk_type) => {
+                write!(f, "Chunk {chunk_type} was not found")
             }
             PngError::InvalidLength => {
                 write!(f, "Invalid chunk length.")
@@ -110,6 +480,27 @@ impl Display for PngError {
             PngError::IncorrectPngHeader => {
                 write!(f, "PNG file header incorrect.")
             }
+            PngError::InvalidPosition(position) => {
+                write!(f, "Invalid chunk position: {position}. Expected: before-iend, after-ihdr, before-idat, after-idat or an index")
+            }
+            PngError::AnchorNotFound(chunk_type) => {
+                write!(f, "Cannot position chunk relative to missing {chunk_type} chunk")
+            }
+            PngError::IndexOutOfBounds(index, len) => {
+                write!(f, "Chunk index {index} out of bounds. Max: {len}")
+            }
+            PngError::OccurrenceNotFound { chunk_type, index, count } => {
+                write!(f, "Chunk {chunk_type} #{index} was not found. There are {count} {chunk_type} chunk(s), numbered from 0")
+            }
+            PngError::ValidationFailed(count) => {
+                write!(f, "Found {count} structural violation(s)")
+            }
+            PngError::NoTrailingData => {
+                write!(f, "There is no data after IEND")
+            }
+            PngError::InvalidParseMode(mode) => {
+                write!(f, "Invalid parse mode: {mode}. Expected: strict, warn or recover")
+            }
         }
     }
 }
@@ -120,7 +511,9 @@ impl std::error::Error for PngError {}
 mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
-    use crate::chunk::Chunk;
+    use crate::chunk::{Chunk, ChunkError};
+    use crate::image_header::{ColorType, InterlaceMethod};
+    use crate::pixels::Pixel;
     use std::str::FromStr;
     use std::convert::TryFrom;
 
@@ -215,6 +608,21 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_invalid_chunk_offset() {
+        let mut bytes = PNG_FILE.to_vec();
+        // Corrupt the CRC of the sRGB chunk, which starts right after the 25-byte IHDR
+        bytes[8 + 25 + 12] ^= 0xFF;
+
+        match Png::try_from(bytes.as_ref()) {
+            Err(Error::Chunk { offset, source: ChunkError::CrcMismatch { chunk_type, .. } }) => {
+                assert_eq!(offset, 33);
+                assert_eq!(chunk_type.to_string(), "sRGB");
+            }
+            _ => panic!("expected CRC mismatch")
+        }
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -231,6 +639,15 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am another first chunk").unwrap());
+        let chunks: Vec<_> = png.chunks_by_type("FrSt").map(|(index, c)| (index, c.data_as_string().unwrap())).collect();
+        assert_eq!(chunks, [(0, "I am the first chunk".to_string()), (3, "I am another first chunk".to_string())]);
+        assert_eq!(png.chunks_by_type("NoPe").count(), 0);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -240,6 +657,46 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_insert_chunk_before_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_chunk(chunk_from_strings("TeSt", "Message").unwrap(), ChunkPosition::BeforeIend).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(&types[types.len() - 2..], ["TeSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_around_anchors() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_chunk(chunk_from_strings("AfHd", "1").unwrap(), ChunkPosition::AfterIhdr).unwrap();
+        png.insert_chunk(chunk_from_strings("BfId", "2").unwrap(), ChunkPosition::BeforeFirstIdat).unwrap();
+        png.insert_chunk(chunk_from_strings("AfId", "3").unwrap(), ChunkPosition::AfterLastIdat).unwrap();
+        png.insert_chunk(chunk_from_strings("FrSt", "4").unwrap(), ChunkPosition::Index(0)).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["FrSt", "IHDR", "AfHd", "sRGB", "gAMA", "pHYs", "BfId", "IDAT", "AfId", "RuSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_missing_anchor() {
+        let mut png = testing_png();
+        let result = png.insert_chunk(chunk_from_strings("TeSt", "Message").unwrap(), ChunkPosition::AfterIhdr);
+        assert!(result.is_err());
+        let result = png.insert_chunk(chunk_from_strings("TeSt", "Message").unwrap(), ChunkPosition::Index(4));
+        assert!(result.is_err());
+
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let result = png.insert_chunk(chunk_from_strings("TeSt", "Message").unwrap(), ChunkPosition::Index(7));
+        assert!(matches!(result, Err(Error::Png(PngError::IndexOutOfBounds(7, 6)))));
+    }
+
+    #[test]
+    fn test_chunk_position_from_str() {
+        assert_eq!(ChunkPosition::from_str("before-iend").unwrap(), ChunkPosition::BeforeIend);
+        assert_eq!(ChunkPosition::from_str("after-idat").unwrap(), ChunkPosition::AfterLastIdat);
+        assert_eq!(ChunkPosition::from_str("3").unwrap(), ChunkPosition::Index(3));
+        assert!(ChunkPosition::from_str("middle").is_err());
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -249,6 +706,21 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "Stale").unwrap());
+        png.append_chunk(chunk_from_strings("miDl", "Newest").unwrap());
+
+        let removed = png.remove_chunks("miDl", Occurrence::Nth(1)).unwrap();
+        assert_eq!(removed[0].data_as_string().unwrap(), "Stale");
+        assert!(matches!(png.remove_chunks("miDl", Occurrence::Nth(2)), Err(Error::Png(PngError::OccurrenceNotFound { count: 2, .. }))));
+
+        assert_eq!(png.remove_chunks("miDl", Occurrence::All).unwrap().len(), 2);
+        assert_eq!(png.chunks().len(), 2);
+        assert!(matches!(png.remove_chunks("miDl", Occurrence::All), Err(Error::Png(PngError::ChunkNotFound(_)))));
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -263,6 +735,121 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_from_file_with_threshold() {
+        let read = Png::from_file("dice.png").unwrap();
+        let mapped = Png::from_file_with_threshold("dice.png", 0).unwrap();
+        assert_eq!(mapped.as_bytes_with_trailing_data(), read.as_bytes_with_trailing_data());
+        assert_eq!(read.as_bytes_with_trailing_data(), std::fs::read("dice.png").unwrap());
+    }
+
+    #[test]
+    fn test_png_ref_borrows_input() {
+        let png = PngRef::try_from(&PNG_FILE[..]).unwrap();
+        let chunk = png.chunk_by_type("RuSt").unwrap();
+        assert_eq!(chunk.data_as_str().unwrap(), "hey");
+        assert!(PNG_FILE.as_ptr_range().contains(&chunk.data().as_ptr()));
+        assert_eq!(png.to_png().as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_png_ref_matches_png() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let png_ref = PngRef::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.to_string(), png_ref.to_string());
+        for (owned, borrowed) in png.chunks().iter().zip(png_ref.chunks()) {
+            assert_eq!(owned.length(), borrowed.length());
+            assert_eq!(owned.chunk_type(), borrowed.chunk_type());
+            assert_eq!(owned.data(), borrowed.data());
+            assert_eq!(owned.crc(), borrowed.crc());
+        }
+    }
+
+    #[test]
+    fn test_png_ref_offset_of() {
+        let png = PngRef::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.offset_of(0), 8);
+        assert_eq!(png.offset_of(1), 8 + 25);
+    }
+
+    #[test]
+    fn test_png_ref_scan_skips_crc() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[8 + 25 + 12] ^= 0xFF;
+
+        assert!(PngRef::try_from(bytes.as_slice()).is_err());
+        let png = PngRef::scan(bytes.as_slice()).unwrap();
+        assert_eq!(png.chunks().len(), 7);
+        assert!(png.chunk_by_type("sRGB").unwrap().verify().is_err());
+        assert!(png.chunk_by_type("RuSt").unwrap().verify().is_ok());
+    }
+
+    #[test]
+    fn test_image_header() {
+        let header = Png::try_from(&PNG_FILE[..]).unwrap().image_header().unwrap();
+        assert_eq!(header.width(), 50);
+        assert_eq!(header.height(), 50);
+        assert_eq!(header.bit_depth(), 8);
+        assert_eq!(header.color_type(), ColorType::Rgba);
+        assert_eq!(header.interlace_method(), InterlaceMethod::None);
+        assert_eq!(PngRef::try_from(&PNG_FILE[..]).unwrap().image_header().unwrap(), header);
+    }
+
+    #[test]
+    fn test_pixels_round_trip() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut pixels = png.pixels().unwrap();
+        assert_eq!((pixels.width(), pixels.height()), (50, 50));
+
+        pixels.set_pixel(7, 3, Pixel::Rgba(1, 2, 3, 4)).unwrap();
+        png.set_pixels(&pixels).unwrap();
+        let bytes = png.as_bytes();
+        let decoded = PngRef::try_from(&bytes[..]).unwrap().pixels().unwrap();
+        assert_eq!(decoded, pixels);
+        assert_eq!(decoded.pixel(7, 3), Some(Pixel::Rgba(1, 2, 3, 4)));
+
+        assert!(matches!(testing_png().pixels(), Err(Error::Header(HeaderError::MissingIhdr))));
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let zip = b"PK\x03\x04 appended archive";
+        let bytes = [&PNG_FILE[..], zip].concat();
+
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.trailing_data(), zip);
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+        assert_eq!(png.as_bytes(), PNG_FILE);
+        assert_eq!(png.as_bytes_with_trailing_data(), bytes);
+        assert_eq!(Png::from_reader(bytes.as_slice()).unwrap().trailing_data(), zip);
+
+        let png_ref = PngRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png_ref.trailing_data(), zip);
+        assert_eq!(png_ref.trailing_offset(), PNG_FILE.len());
+        assert!(Png::try_from(&PNG_FILE[..]).unwrap().trailing_data().is_empty());
+    }
+
+    #[test]
+    fn test_image_header_missing() {
+        let png = testing_png();
+        assert!(matches!(png.image_header(), Err(Error::Header(HeaderError::MissingIhdr))));
+    }
+
+    #[test]
+    fn test_validate_image_file() {
+        // The test image carries an unknown critical RuSt chunk
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let rust_type = ChunkType::from_str("RuSt").unwrap();
+        assert_eq!(png.validate(), vec![Violation::UnknownCriticalChunk { chunk_type: rust_type, index: 5 }]);
+
+        png.remove_chunk("RuSt").unwrap();
+        assert_eq!(png.validate(), vec![]);
+
+        png.append_chunk(chunk_from_strings("teSt", "Message").unwrap());
+        let test_type = ChunkType::from_str("teSt").unwrap();
+        assert_eq!(png.validate(), vec![Violation::ChunkAfterIend { chunk_type: test_type, index: 6 }]);
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::chunk::{crc_of, read_u32, Chunk};
+use crate::chunk_type::ChunkType;
+use crate::image_header::ImageHeader;
+use crate::parse::{DiagnosticKind, ParseOptions, Parsed};
+use crate::png::Png;
+use crate::{compress, pixels, Result};
+
+/// Largest width or height tried when searching for the dimensions an IHDR CRC was computed over.
+pub const MAX_GUESSED_DIMENSION: u32 = 1 << 14;
+
+/// A complete `IEND` chunk, whose data is always empty.
+const IEND_BYTES: [u8; 12] = [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82];
+
+/// The result of [`repair`]: the fixed PNG and every change made to get there.
+pub struct Repaired {
+    pub png: Png,
+    pub changes: Vec<Change>
+}
+
+/// A change made by [`repair`], at a byte offset in the damaged input.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Change {
+    pub offset: usize,
+    pub kind: ChangeKind
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ChangeKind {
+    /// The stored CRC was replaced with the one computed from the data.
+    Crc { chunk_type: ChunkType, stored: u32, computed: u32 },
+    /// IHDR held other dimensions than the ones its CRC was computed over.
+    Dimensions { from: (u32, u32), to: (u32, u32) },
+    /// The input ended inside this chunk, which was kept with the bytes that were there.
+    Truncated { chunk_type: ChunkType, declared: u32, available: usize },
+    /// Bytes that were not part of any chunk were dropped.
+    Skipped { length: usize },
+    /// A cut-off `IEND` was rewritten in full.
+    IendCompleted,
+    IendAdded
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Byte {}: ", self.offset)?;
+        match &self.kind {
+            ChangeKind::Crc { chunk_type, stored, computed } => {
+                write!(f, "rewrote {chunk_type} CRC {stored:08x} as {computed:08x}")
+            }
+            ChangeKind::Dimensions { from: (width, height), to: (new_width, new_height) } => {
+                write!(f, "restored IHDR dimensions {width}x{height} to {new_width}x{new_height}, which match its CRC")
+            }
+            ChangeKind::Truncated { chunk_type, declared, available } => {
+                write!(f, "kept {available} of {declared} bytes of truncated {chunk_type} chunk")
+            }
+            ChangeKind::Skipped { length } => {
+                write!(f, "dropped {length} bytes that are not a chunk")
+            }
+            ChangeKind::IendCompleted => {
+                write!(f, "completed truncated IEND chunk")
+            }
+            ChangeKind::IendAdded => {
+                write!(f, "added missing IEND chunk")
+            }
+        }
+    }
+}
+
+/// Parses `bytes` in [recover mode](crate::parse::ParseMode::Recover) and fixes what it reports.
+///
+/// Writing the returned [`Png`] recomputes every CRC. When the IHDR CRC does not match, widths and heights
+/// up to [`MAX_GUESSED_DIMENSION`] are searched for ones it does match before the CRC is rewritten.
+pub fn repair(bytes: &[u8]) -> Result<Repaired> {
+    let Parsed { png, diagnostics, .. } = Png::parse_with(bytes, &ParseOptions::recover())?;
+    let mut chunks = png.chunks().to_vec();
+    let mut changes = Vec::new();
+    let mut iend_completed = false;
+
+    for diagnostic in diagnostics {
+        let offset = diagnostic.offset;
+        let kind = match diagnostic.kind {
+            DiagnosticKind::CrcMismatch { index, chunk_type, stored, computed } => {
+                let guess = if chunk_type.to_string() == "IHDR" {
+                    guess_dimensions(chunks[index].data(), stored, &idat(&chunks))
+                } else {
+                    None
+                };
+                match guess {
+                    Some((width, height)) => {
+                        let mut data = chunks[index].data().to_vec();
+                        let from = (read_u32(&data[0..4]), read_u32(&data[4..8]));
+                        data[0..4].copy_from_slice(&width.to_be_bytes());
+                        data[4..8].copy_from_slice(&height.to_be_bytes());
+                        chunks[index] = Chunk::new(chunk_type, data);
+                        ChangeKind::Dimensions { from, to: (width, height) }
+                    }
+                    None => ChangeKind::Crc { chunk_type, stored, computed }
+                }
+            }
+            DiagnosticKind::Truncated { index, chunk_type, .. } if chunk_type.to_string() == "IEND" => {
+                chunks[index] = Chunk::new(chunk_type, Vec::new());
+                iend_completed = true;
+                ChangeKind::IendCompleted
+            }
+            DiagnosticKind::Truncated { chunk_type, declared, available, .. } => {
+                ChangeKind::Truncated { chunk_type, declared, available }
+            }
+            DiagnosticKind::Skipped { length } if offset + length == bytes.len() && IEND_BYTES.starts_with(&bytes[offset..]) => {
+                iend_completed = true;
+                ChangeKind::IendCompleted
+            }
+            DiagnosticKind::Skipped { length } => ChangeKind::Skipped { length },
+            DiagnosticKind::MissingIend => {
+                if chunks.last().is_none_or(|c| c.chunk_type().to_string() != "IEND") {
+                    chunks.push(Chunk::try_from(&IEND_BYTES[..])?);
+                }
+                if iend_completed { continue }
+                ChangeKind::IendAdded
+            }
+        };
+        changes.push(Change { offset, kind });
+    }
+
+    let mut repaired = Png::from_chunks(chunks);
+    repaired.set_trailing_data(png.trailing_data().to_vec());
+    Ok(Repaired { png: repaired, changes })
+}
+
+/// The concatenated data of the IDAT chunks.
+fn idat(chunks: &[Chunk]) -> Vec<u8> {
+    chunks.iter()
+        .filter(|c| c.chunk_type().to_string() == "IDAT")
+        .flat_map(|c| c.data().iter().copied())
+        .collect()
+}
+
+/// Searches for a width and height that give IHDR `data` the CRC `stored`.
+///
+/// CRC-32 is affine over messages of one length, so the CRC of any width and height combines the CRC
+/// of the width alone with that of the height alone, and one table of heights covers every pair.
+/// A pair whose image data length matches the inflated IDAT wins; if the IDAT cannot be inflated or
+/// matches no pair, only pairs that keep one of the two dimensions are trusted, since random matches
+/// are likely otherwise.
+fn guess_dimensions(data: &[u8], stored: u32, idat: &[u8]) -> Option<(u32, u32)> {
+    if data.len() != ImageHeader::LENGTH { return None }
+    let ihdr = ChunkType::from_str("IHDR").ok()?;
+    let crc_with = |width: u32, height: u32| {
+        let mut data = data.to_vec();
+        data[0..4].copy_from_slice(&width.to_be_bytes());
+        data[4..8].copy_from_slice(&height.to_be_bytes());
+        crc_of(&ihdr, &data)
+    };
+
+    let base = crc_with(0, 0);
+    let heights: HashMap<u32, u32> = (1..=MAX_GUESSED_DIMENSION).map(|h| (crc_with(0, h) ^ base, h)).collect();
+    let candidates: Vec<(u32, u32)> = (1..=MAX_GUESSED_DIMENSION)
+        .filter_map(|w| heights.get(&(stored ^ crc_with(w, 0))).map(|&h| (w, h)))
+        .collect();
+
+    let header_with = |width: u32, height: u32| {
+        let mut data = data.to_vec();
+        data[0..4].copy_from_slice(&width.to_be_bytes());
+        data[4..8].copy_from_slice(&height.to_be_bytes());
+        ImageHeader::try_from(data.as_slice()).ok()
+    };
+    let expected_len = |&(width, height): &(u32, u32)| header_with(width, height).and_then(|h| pixels::filtered_len(&h));
+
+    let limit = candidates.iter().filter_map(expected_len).max();
+    if let Some(inflated) = limit.and_then(|limit| compress::inflated_len(idat, limit as u64).ok()) {
+        if let Some(&found) = candidates.iter().find(|c| expected_len(c) == Some(inflated as usize)) {
+            return Some(found);
+        }
+    }
+
+    let (width, height) = (read_u32(&data[0..4]), read_u32(&data[4..8]));
+    candidates.into_iter().find(|&(w, h)| w == width || h == height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_header::{ColorType, InterlaceMethod};
+    use crate::pixels::PixelBuffer;
+
+    const DICE: &[u8] = include_bytes!("../dice.png");
+
+    fn small_png() -> Png {
+        let header = ImageHeader::new(37, 21, 8, ColorType::Rgb, InterlaceMethod::None).unwrap();
+        let idat = Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new());
+        let mut png = Png::from_chunks(vec![header.to_chunk(), idat, Chunk::try_from(&IEND_BYTES[..]).unwrap()]);
+        png.set_pixels(&PixelBuffer::new(header, vec![7; 37 * 21 * 3]).unwrap()).unwrap();
+        png
+    }
+
+    /// Overwrites the height in IHDR without touching its CRC.
+    fn tamper_height(bytes: &mut [u8], height: u32) {
+        bytes[20..24].copy_from_slice(&height.to_be_bytes());
+    }
+
+    #[test]
+    fn test_intact_file() {
+        let repaired = repair(DICE).unwrap();
+        assert!(repaired.changes.is_empty());
+        assert_eq!(repaired.png.as_bytes(), DICE);
+    }
+
+    #[test]
+    fn test_rewrites_crc() {
+        let mut bytes = DICE.to_vec();
+        *bytes.last_mut().unwrap() ^= 0xFF;
+
+        let repaired = repair(&bytes).unwrap();
+        assert_eq!(repaired.changes.len(), 1);
+        assert_eq!(repaired.changes[0].offset, DICE.len() - IEND_BYTES.len());
+        assert!(matches!(repaired.changes[0].kind, ChangeKind::Crc { .. }));
+        assert_eq!(repaired.png.as_bytes(), DICE);
+    }
+
+    #[test]
+    fn test_restores_dimensions() {
+        let mut bytes = DICE.to_vec();
+        tamper_height(&mut bytes, 200);
+
+        let repaired = repair(&bytes).unwrap();
+        assert_eq!(repaired.changes, [Change { offset: 8, kind: ChangeKind::Dimensions { from: (550, 200), to: (550, 320) } }]);
+        assert_eq!(repaired.png.as_bytes(), DICE);
+    }
+
+    #[test]
+    fn test_restores_dimensions_by_image_data_length() {
+        let original = small_png().as_bytes();
+        let mut bytes = original.clone();
+        bytes[16..20].copy_from_slice(&1000u32.to_be_bytes());
+        tamper_height(&mut bytes, 1000);
+
+        let repaired = repair(&bytes).unwrap();
+        assert_eq!(repaired.changes[0].kind, ChangeKind::Dimensions { from: (1000, 1000), to: (37, 21) });
+        assert_eq!(repaired.png.as_bytes(), original);
+    }
+
+    #[test]
+    fn test_restores_dimensions_when_image_data_length_matches_none() {
+        let png = Png::try_from(DICE).unwrap();
+        let mut chunks: Vec<Chunk> = png.chunks().iter().filter(|c| c.chunk_type().to_string() != "IDAT").cloned().collect();
+        let idat = Chunk::new(ChunkType::from_str("IDAT").unwrap(), compress::deflate(&[0; 1000]).unwrap());
+        chunks.insert(chunks.len() - 1, idat);
+        let mut bytes = Png::from_chunks(chunks).as_bytes();
+        tamper_height(&mut bytes, 200);
+
+        let repaired = repair(&bytes).unwrap();
+        assert_eq!(repaired.changes, [Change { offset: 8, kind: ChangeKind::Dimensions { from: (550, 200), to: (550, 320) } }]);
+    }
+
+    #[test]
+    fn test_fixes_iend() {
+        let iend_at = DICE.len() - IEND_BYTES.len();
+        for cut in [0, 3, 6, 10] {
+            let repaired = repair(&DICE[..iend_at + cut]).unwrap();
+            let expected = if cut == 0 { ChangeKind::IendAdded } else { ChangeKind::IendCompleted };
+            assert_eq!(repaired.changes, [Change { offset: iend_at, kind: expected }], "cut at {cut}");
+            assert_eq!(repaired.png.as_bytes(), DICE);
+        }
+    }
+
+    #[test]
+    fn test_reports_truncated_chunk() {
+        let mut png = small_png();
+        png.retain_chunks(|c| c.chunk_type().to_string() != "IEND");
+        let bytes = png.as_bytes();
+
+        let repaired = repair(&bytes[..bytes.len() - 10]).unwrap();
+        let kinds: Vec<_> = repaired.changes.iter().map(|c| c.kind.clone()).collect();
+        assert!(matches!(kinds[0], ChangeKind::Truncated { .. }));
+        assert_eq!(kinds[1], ChangeKind::IendAdded);
+        assert_eq!(repaired.png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+    }
+}
@@ -0,0 +1,461 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signer, Verifier};
+use sha2::{Digest, Sha256};
+
+use crate::chunk::{check_type, read_u32, Chunk};
+use crate::chunk_type::ChunkType;
+use crate::crypto::{parse_key, to_hex, CryptoError};
+use crate::png::{ChunkPosition, Png};
+use crate::{Error, Result};
+
+/// Type of the private chunk holding the signature.
+pub const SIGNATURE_CHUNK: &str = "sgNT";
+
+/// Chunk types covered by every signature: the image data and the standard text chunks.
+pub const DEFAULT_CHUNK_TYPES: [&str; 6] = ["IHDR", "PLTE", "IDAT", "tEXt", "zTXt", "iTXt"];
+
+const VERSION: u8 = 1;
+const CONTEXT: &[u8] = b"pngcoder signature";
+const DIGEST_LEN: usize = 32;
+const ENTRY_LEN: usize = 4 + DIGEST_LEN;
+const SIGNATURE_LEN: usize = 64;
+
+const SIGNING_KEY_PREFIX: &str = "PNGC-SIGNING-KEY-";
+const VERIFYING_KEY_PREFIX: &str = "PNGC-VERIFYING-KEY-";
+
+/// An Ed25519 private key used by [`sign`].
+///
+/// Its text form is `PNGC-SIGNING-KEY-` followed by 64 hex digits.
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+impl SigningKey {
+    pub fn generate() -> Self {
+        SigningKey(ed25519_dalek::SigningKey::generate(&mut OsRng))
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.verifying_key())
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(SigningKey(ed25519_dalek::SigningKey::from_bytes(&parse_key(s, SIGNING_KEY_PREFIX)?)))
+    }
+}
+
+impl Display for SigningKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{SIGNING_KEY_PREFIX}{}", to_hex(self.0.as_bytes()))
+    }
+}
+
+/// An Ed25519 public key, stored in the signature chunk and checked by [`verify`].
+///
+/// Its text form is `PNGC-VERIFYING-KEY-` followed by 64 hex digits.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct VerifyingKey(ed25519_dalek::VerifyingKey);
+
+impl VerifyingKey {
+    fn from_bytes(bytes: &[u8; 32]) -> Result<Self> {
+        ed25519_dalek::VerifyingKey::from_bytes(bytes)
+            .map(VerifyingKey)
+            .map_err(|_| CryptoError::InvalidKey(VERIFYING_KEY_PREFIX.trim_end_matches('-').to_string()).into())
+    }
+}
+
+impl FromStr for VerifyingKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        VerifyingKey::from_bytes(&parse_key(s, VERIFYING_KEY_PREFIX)?)
+    }
+}
+
+impl Display for VerifyingKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{VERIFYING_KEY_PREFIX}{}", to_hex(self.0.as_bytes()))
+    }
+}
+
+/// The SHA-256 hash of a chunk's type, length and data, the same fields its CRC covers plus the length.
+pub fn chunk_digest(chunk: &Chunk) -> [u8; DIGEST_LEN] {
+    Sha256::new()
+        .chain_update(chunk.chunk_type().bytes())
+        .chain_update(chunk.length().to_be_bytes())
+        .chain_update(chunk.data())
+        .finalize()
+        .into()
+}
+
+/// The digest a signature covers: the selected chunk types followed by the type and
+/// [`chunk_digest`] of every chunk of those types, in file order.
+pub fn canonical_digest(png: &Png, chunk_types: &[ChunkType]) -> [u8; DIGEST_LEN] {
+    digest_manifest(chunk_types, &manifest(png, chunk_types))
+}
+
+/// Signs `png` with `key`, replacing any existing signature.
+///
+/// The signature covers [`DEFAULT_CHUNK_TYPES`] and `extra_chunk_types`, and is stored in a
+/// [`SIGNATURE_CHUNK`] before `IEND`.
+pub fn sign(png: &mut Png, key: &SigningKey, extra_chunk_types: &[ChunkType]) -> Result<()> {
+    while png.remove_chunk(SIGNATURE_CHUNK).is_ok() {}
+
+    let mut chunk_types: Vec<ChunkType> = DEFAULT_CHUNK_TYPES
+        .iter()
+        .map(|t| ChunkType::from_str(t).unwrap())
+        .collect();
+    for chunk_type in extra_chunk_types {
+        check_type(chunk_type.bytes())?;
+        if chunk_type.to_string() == SIGNATURE_CHUNK { return Err(SignatureError::SelfReference.into()) }
+        if !chunk_types.contains(chunk_type) {
+            chunk_types.push(chunk_type.clone());
+        }
+    }
+    if chunk_types.len() > u8::MAX as usize { return Err(SignatureError::TooManyChunkTypes(chunk_types.len()).into()) }
+
+    let entries = manifest(png, &chunk_types);
+    let verifying_key = key.verifying_key();
+    let message = signed_message(&verifying_key, &digest_manifest(&chunk_types, &entries));
+
+    let mut data = vec![VERSION];
+    data.extend(verifying_key.0.as_bytes());
+    data.push(chunk_types.len() as u8);
+    for chunk_type in &chunk_types {
+        data.extend(chunk_type.bytes());
+    }
+    data.extend((entries.len() as u32).to_be_bytes());
+    for (chunk_type, digest) in &entries {
+        data.extend(chunk_type.bytes());
+        data.extend(digest);
+    }
+    data.extend(key.0.sign(&message).to_bytes());
+
+    let chunk = Chunk::try_new(ChunkType::from_str(SIGNATURE_CHUNK).unwrap(), data)?;
+    png.insert_chunk(chunk, ChunkPosition::BeforeIend)
+}
+
+/// Checks the signature of `png` and compares the signed chunks with the current ones.
+///
+/// Fails if there is no signature or it does not match its own manifest. Chunks that changed
+/// since signing are reported in the returned [`Verification`], not as an error.
+pub fn verify(png: &Png) -> Result<Verification> {
+    let chunk = png.chunk_by_type(SIGNATURE_CHUNK).ok_or(SignatureError::Unsigned)?;
+    let signed = SignedManifest::parse(chunk.data())?;
+
+    let message = signed_message(&signed.signer, &digest_manifest(&signed.chunk_types, &signed.entries));
+    signed.signer.0
+        .verify(&message, &signed.signature)
+        .map_err(|_| SignatureError::BadSignature)?;
+
+    let mut changes = Vec::new();
+    for chunk_type in &signed.chunk_types {
+        let before: Vec<_> = signed.entries.iter().filter(|(t, _)| t == chunk_type).map(|(_, d)| d).collect();
+        let after: Vec<_> = png.chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.chunk_type() == chunk_type)
+            .collect();
+
+        for (occurrence, (index, chunk)) in after.iter().enumerate() {
+            let kind = match before.get(occurrence) {
+                Some(digest) if **digest == chunk_digest(chunk) => continue,
+                Some(_) => ChangeKind::Changed,
+                None => ChangeKind::Added
+            };
+            changes.push(ChunkChange { kind, chunk_type: chunk_type.clone(), occurrence, index: Some(*index) });
+        }
+        for occurrence in after.len()..before.len() {
+            changes.push(ChunkChange { kind: ChangeKind::Removed, chunk_type: chunk_type.clone(), occurrence, index: None });
+        }
+    }
+    changes.sort_by_key(|c| c.index.unwrap_or(usize::MAX));
+
+    let unsigned = png.chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !signed.chunk_types.contains(c.chunk_type()))
+        .filter(|(_, c)| !matches!(c.chunk_type().to_string().as_str(), SIGNATURE_CHUNK | "IEND"))
+        .map(|(index, c)| (index, c.chunk_type().clone()))
+        .collect();
+
+    Ok(Verification { signer: signed.signer, chunk_types: signed.chunk_types, changes, unsigned })
+}
+
+/// The result of [`verify`]: who signed the image and what changed since.
+#[derive(Debug)]
+pub struct Verification {
+    pub signer: VerifyingKey,
+    /// Chunk types the signature covers.
+    pub chunk_types: Vec<ChunkType>,
+    /// Signed chunks that were changed, added or removed after signing.
+    pub changes: Vec<ChunkChange>,
+    /// Index and type of chunks the signature does not cover.
+    pub unsigned: Vec<(usize, ChunkType)>
+}
+
+impl Verification {
+    /// Whether every covered chunk is unchanged.
+    pub fn is_intact(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChangeKind {
+    Changed,
+    Added,
+    Removed
+}
+
+/// A covered chunk that differs from the signed manifest.
+///
+/// `occurrence` counts chunks of the same type, `index` is the position in the current file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChunkChange {
+    pub kind: ChangeKind,
+    pub chunk_type: ChunkType,
+    pub occurrence: usize,
+    pub index: Option<usize>
+}
+
+impl Display for ChunkChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {} #{}", self.kind, self.chunk_type, self.occurrence + 1)?;
+        if let Some(index) = self.index {
+            write!(f, " (chunk {index})")?;
+        }
+        Ok(())
+    }
+}
+
+struct SignedManifest {
+    signer: VerifyingKey,
+    chunk_types: Vec<ChunkType>,
+    entries: Vec<(ChunkType, [u8; DIGEST_LEN])>,
+    signature: ed25519_dalek::Signature
+}
+
+impl SignedManifest {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let malformed = || Error::from(SignatureError::Malformed);
+        if data.is_empty() { return Err(malformed()) }
+        if data[0] != VERSION { return Err(SignatureError::UnsupportedVersion(data[0]).into()) }
+        if data.len() < 34 + SIGNATURE_LEN { return Err(malformed()) }
+
+        let signer = VerifyingKey::from_bytes(data[1..33].try_into().unwrap()).map_err(|_| malformed())?;
+        let type_count = data[33] as usize;
+        let types_end = 34 + type_count * 4;
+        if data.len() < types_end + 4 + SIGNATURE_LEN { return Err(malformed()) }
+
+        let chunk_types = data[34..types_end]
+            .chunks_exact(4)
+            .map(|t| check_type([t[0], t[1], t[2], t[3]]))
+            .collect::<Result<Vec<_>>>()
+            .map_err(|_| malformed())?;
+
+        let entry_count = read_u32(&data[types_end..types_end + 4]) as usize;
+        let entries_start = types_end + 4;
+        let entries_len = entry_count.checked_mul(ENTRY_LEN).ok_or_else(malformed)?;
+        if data.len() != entries_start + entries_len + SIGNATURE_LEN { return Err(malformed()) }
+
+        let mut entries = Vec::with_capacity(entry_count);
+        for entry in data[entries_start..entries_start + entries_len].chunks_exact(ENTRY_LEN) {
+            let chunk_type = check_type([entry[0], entry[1], entry[2], entry[3]]).map_err(|_| malformed())?;
+            entries.push((chunk_type, entry[4..].try_into().unwrap()));
+        }
+
+        let signature = ed25519_dalek::Signature::from_bytes(data[data.len() - SIGNATURE_LEN..].try_into().unwrap());
+        Ok(Self { signer, chunk_types, entries, signature })
+    }
+}
+
+fn manifest(png: &Png, chunk_types: &[ChunkType]) -> Vec<(ChunkType, [u8; DIGEST_LEN])> {
+    png.chunks()
+        .iter()
+        .filter(|c| chunk_types.contains(c.chunk_type()))
+        .map(|c| (c.chunk_type().clone(), chunk_digest(c)))
+        .collect()
+}
+
+fn digest_manifest(chunk_types: &[ChunkType], entries: &[(ChunkType, [u8; DIGEST_LEN])]) -> [u8; DIGEST_LEN] {
+    let mut hasher = Sha256::new();
+    for chunk_type in chunk_types {
+        hasher.update(chunk_type.bytes());
+    }
+    for (chunk_type, digest) in entries {
+        hasher.update(chunk_type.bytes());
+        hasher.update(digest);
+    }
+    hasher.finalize().into()
+}
+
+fn signed_message(signer: &VerifyingKey, digest: &[u8; DIGEST_LEN]) -> Vec<u8> {
+    [CONTEXT, &[VERSION], signer.0.as_bytes(), digest].concat()
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SignatureError {
+    /// There is no signature chunk.
+    Unsigned,
+    Malformed,
+    UnsupportedVersion(u8),
+    /// The signature does not match the signed manifest.
+    BadSignature,
+    /// The image was signed by a different key than the expected one. Holds the signer's key.
+    UntrustedSigner(String),
+    /// The number of covered chunks that changed after signing.
+    Modified(usize),
+    SelfReference,
+    TooManyChunkTypes(usize)
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::Unsigned => {
+                write!(f, "No {SIGNATURE_CHUNK} signature chunk found")
+            }
+            SignatureError::Malformed => {
+                write!(f, "Signature chunk is malformed")
+            }
+            SignatureError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported signature version: {version}")
+            }
+            SignatureError::BadSignature => {
+                write!(f, "Signature is not valid")
+            }
+            SignatureError::UntrustedSigner(signer) => {
+                write!(f, "Signed by an unexpected key: {signer}")
+            }
+            SignatureError::Modified(count) => {
+                write!(f, "{count} signed chunk(s) changed after signing")
+            }
+            SignatureError::SelfReference => {
+                write!(f, "The signature chunk cannot sign itself")
+            }
+            SignatureError::TooManyChunkTypes(count) => {
+                write!(f, "Too many chunk types to sign: {count}. Max: {}", u8::MAX)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.bytes().collect())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk("IHDR", "header"),
+            chunk("IDAT", "first"),
+            chunk("IDAT", "second"),
+            chunk("ruSt", "note"),
+            chunk("IEND", ""),
+        ])
+    }
+
+    fn signed_png(key: &SigningKey) -> Png {
+        let mut png = testing_png();
+        sign(&mut png, key, &[ChunkType::from_str("ruSt").unwrap()]).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::generate();
+        let png = signed_png(&key);
+        assert_eq!(png.chunks()[4].chunk_type().to_string(), SIGNATURE_CHUNK);
+
+        let verification = verify(&png).unwrap();
+        assert!(verification.is_intact());
+        assert_eq!(verification.signer, key.verifying_key());
+        assert!(verification.unsigned.is_empty());
+
+        // Signing again replaces the signature
+        let mut png = png;
+        sign(&mut png, &key, &[]).unwrap();
+        assert_eq!(png.chunks().iter().filter(|c| c.chunk_type().to_string() == SIGNATURE_CHUNK).count(), 1);
+    }
+
+    #[test]
+    fn test_verify_reports_changes() {
+        let key = SigningKey::generate();
+        let png = signed_png(&key);
+
+        let mut chunks = png.chunks().to_vec();
+        chunks[2] = chunk("IDAT", "tampered");
+        chunks.insert(3, chunk("IDAT", "extra"));
+        chunks.insert(1, chunk("tIME", "now"));
+        chunks.retain(|c| c.chunk_type().to_string() != "ruSt");
+        let verification = verify(&Png::from_chunks(chunks)).unwrap();
+
+        let changes: Vec<_> = verification.changes.iter().map(|c| (c.kind, c.chunk_type.to_string(), c.occurrence)).collect();
+        assert_eq!(changes, [
+            (ChangeKind::Changed, "IDAT".to_string(), 1),
+            (ChangeKind::Added, "IDAT".to_string(), 2),
+            (ChangeKind::Removed, "ruSt".to_string(), 0),
+        ]);
+        assert_eq!(verification.unsigned, [(1, ChunkType::from_str("tIME").unwrap())]);
+    }
+
+    #[test]
+    fn test_verify_rejects_forged_manifest() {
+        let png = signed_png(&SigningKey::generate());
+        let mut chunks = png.chunks().to_vec();
+        let mut data = chunks[4].data().to_vec();
+        let last_entry = data.len() - SIGNATURE_LEN - 1;
+        data[last_entry] ^= 1;
+        chunks[4] = Chunk::new(ChunkType::from_str(SIGNATURE_CHUNK).unwrap(), data);
+
+        let result = verify(&Png::from_chunks(chunks));
+        assert!(matches!(result, Err(Error::Signature(SignatureError::BadSignature))));
+        assert!(matches!(verify(&testing_png()), Err(Error::Signature(SignatureError::Unsigned))));
+    }
+
+    #[test]
+    fn test_verify_rejects_invalid_chunk_types() {
+        let invalid = ChunkType::try_from([0xC3, 0x28, b'a', b'b']).unwrap();
+        assert!(sign(&mut testing_png(), &SigningKey::generate(), std::slice::from_ref(&invalid)).is_err());
+
+        // The signer key is embedded, so anyone can produce a correctly signed manifest like this one
+        let key = SigningKey::generate();
+        let verifying_key = key.verifying_key();
+        let entries = vec![(invalid.clone(), [0; DIGEST_LEN])];
+        let message = signed_message(&verifying_key, &digest_manifest(std::slice::from_ref(&invalid), &entries));
+        let data = [
+            &[VERSION][..],
+            verifying_key.0.as_bytes(),
+            &[1],
+            &invalid.bytes(),
+            &1u32.to_be_bytes(),
+            &invalid.bytes(),
+            &[0; DIGEST_LEN],
+            &key.0.sign(&message).to_bytes()
+        ].concat();
+
+        let mut png = testing_png();
+        png.insert_chunk(Chunk::new(ChunkType::from_str(SIGNATURE_CHUNK).unwrap(), data), ChunkPosition::BeforeIend).unwrap();
+        assert!(matches!(verify(&png), Err(Error::Signature(SignatureError::Malformed))));
+    }
+
+    #[test]
+    fn test_key_text_round_trip() {
+        let key = SigningKey::generate();
+        let parsed = SigningKey::from_str(&key.to_string()).unwrap();
+        assert_eq!(parsed.verifying_key(), key.verifying_key());
+        assert_eq!(VerifyingKey::from_str(&key.verifying_key().to_string()).unwrap(), key.verifying_key());
+    }
+}
@@ -0,0 +1,407 @@
+use std::fmt::{Display, Formatter};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use sha2::{Digest, Sha256};
+
+use crate::chunk::{check_type, read_u32};
+use crate::chunk_type::ChunkType;
+use crate::image_header::{ColorType, ImageHeader};
+use crate::pixels::PixelBuffer;
+use crate::Result;
+
+/// Marks a payload embedded by [`embed`].
+pub const MAGIC: [u8; 4] = *b"PNGL";
+
+/// Highest matrix encoding strength: 8 payload bits in every 255 samples.
+pub const MAX_STRENGTH: u8 = 8;
+
+/// magic, tag, strength and payload length.
+const HEADER_LEN: usize = 4 + 4 + 1 + 4;
+
+/// Fixed salt for deriving the embedding order, which has nowhere to store a random one.
+const ORDER_SALT: &[u8] = b"pngcoder LSB embedding order";
+
+/// How [`embed`] spreads a payload over the samples.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Options {
+    seed: Option<[u8; 32]>,
+    strength: u8
+}
+
+impl Default for Options {
+    /// Raster order, one payload bit per sample.
+    fn default() -> Self {
+        Self { seed: None, strength: 1 }
+    }
+}
+
+impl Options {
+    /// Visits the samples in a pseudo-random order derived from `passphrase` with Argon2id,
+    /// so the payload is scattered over the image and cannot be found without it.
+    pub fn keyed(passphrase: &[u8]) -> Result<Self> {
+        let mut seed = [0; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::default())
+            .hash_password_into(passphrase, ORDER_SALT, &mut seed)
+            .map_err(|e| StegoError::KeyDerivation(e.to_string()))?;
+        Ok(Self { seed: Some(seed), strength: 1 })
+    }
+
+    /// Matrix encoding with a Hamming code: every group of 2^`strength` - 1 samples carries
+    /// `strength` payload bits with at most one sample changed.
+    ///
+    /// Higher strengths change fewer samples per payload bit, and so are harder to detect,
+    /// at the cost of capacity. 1, the default, is plain LSB replacement.
+    pub fn with_strength(mut self, strength: u8) -> Result<Self> {
+        if !(1..=MAX_STRENGTH).contains(&strength) {
+            return Err(StegoError::InvalidStrength(strength).into());
+        }
+        self.strength = strength;
+        Ok(self)
+    }
+
+    pub fn strength(&self) -> u8 {
+        self.strength
+    }
+
+    pub fn is_keyed(&self) -> bool {
+        self.seed.is_some()
+    }
+}
+
+/// Payload bytes that fit in the least significant bits of the image's colour samples.
+///
+/// Alpha samples are left alone, and indexed images are not supported because neighbouring
+/// palette indices can be entirely different colours.
+pub fn capacity(header: &ImageHeader, options: &Options) -> Result<usize> {
+    Ok(payload_capacity(carrier_count(header)?, options.strength))
+}
+
+/// Writes `payload` into the least significant bits of the colour samples, in the order and
+/// with the matrix encoding `options` pick. Returns the number of samples changed.
+///
+/// `tag` labels the payload, like the type of the chunk it would otherwise be stored in.
+pub fn embed(pixels: &mut PixelBuffer, tag: &ChunkType, payload: &[u8], options: &Options) -> Result<usize> {
+    check_type(tag.bytes())?;
+    let available = capacity(pixels.header(), options)?;
+    if payload.len() > available || payload.len() > u32::MAX as usize {
+        return Err(StegoError::CapacityExceeded { needed: payload.len(), available }.into());
+    }
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend(MAGIC);
+    header.extend(tag.bytes());
+    header.push(options.strength);
+    header.extend((payload.len() as u32).to_be_bytes());
+
+    let positions = carrier_positions(pixels.header(), pixels.stride(), options)?;
+    let data = pixels.as_bytes_mut();
+    let mut carriers = positions.into_iter();
+    let mut changed = 0;
+
+    // The header is always one bit per sample, so it can be read before the strength is known
+    for (bit, (index, shift)) in bits(&header).zip(carriers.by_ref()) {
+        if (data[index] >> shift) & 1 != bit {
+            data[index] ^= 1 << shift;
+            changed += 1;
+        }
+    }
+
+    let strength = options.strength as usize;
+    let group_len = (1 << strength) - 1;
+    let mut payload_bits = bits(payload).peekable();
+    while payload_bits.peek().is_some() {
+        // The last group is padded with zero bits
+        let message = (0..strength).fold(0, |m, _| (m << 1) | payload_bits.next().unwrap_or(0) as usize);
+        let group: Vec<(usize, u8)> = carriers.by_ref().take(group_len).collect();
+        let flip = syndrome(data, &group) ^ message;
+        if flip != 0 {
+            let (index, shift) = group[flip - 1];
+            data[index] ^= 1 << shift;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+/// Reads a payload written by [`embed`] with the same `tag` and order.
+///
+/// The strength is recorded with the payload, so only the order of `options` matters.
+pub fn extract(pixels: &PixelBuffer, tag: &ChunkType, options: &Options) -> Result<Vec<u8>> {
+    let carrier_count = carrier_count(pixels.header())?;
+    let data = pixels.as_bytes();
+    let mut carriers = carrier_positions(pixels.header(), pixels.stride(), options)?.into_iter();
+
+    if carrier_count < HEADER_LEN * 8 { return Err(StegoError::NoPayload.into()) }
+    let header = bytes(carriers.by_ref().take(HEADER_LEN * 8).map(|(index, shift)| (data[index] >> shift) & 1), HEADER_LEN);
+    if header[..4] != MAGIC { return Err(StegoError::NoPayload.into()) }
+
+    // Random bits that happen to spell the magic must not be printed as a tag
+    let found = check_type([header[4], header[5], header[6], header[7]]).map_err(|_| StegoError::NoPayload)?;
+    if &found != tag {
+        return Err(StegoError::TagMismatch { expected: tag.to_string(), found: found.to_string() }.into());
+    }
+
+    let strength = header[8] as usize;
+    if !(1..=MAX_STRENGTH as usize).contains(&strength) { return Err(StegoError::NoPayload.into()) }
+    let length = read_u32(&header[9..13]) as usize;
+    if length > payload_capacity(carrier_count, header[8]) { return Err(StegoError::NoPayload.into()) }
+
+    let group_len = (1 << strength) - 1;
+    let groups = (length * 8).div_ceil(strength);
+    let payload_bits = (0..groups).flat_map(|_| {
+        let group: Vec<(usize, u8)> = carriers.by_ref().take(group_len).collect();
+        let message = syndrome(data, &group);
+        (0..strength).rev().map(move |i| ((message >> i) & 1) as u8)
+    });
+    Ok(bytes(payload_bits, length))
+}
+
+/// Index of the sample to flip, counting from 1, so the group's syndrome becomes zero.
+fn syndrome(data: &[u8], group: &[(usize, u8)]) -> usize {
+    group
+        .iter()
+        .enumerate()
+        .filter(|(_, &(index, shift))| (data[index] >> shift) & 1 == 1)
+        .fold(0, |syndrome, (i, _)| syndrome ^ (i + 1))
+}
+
+fn bits(bytes: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    bytes.iter().flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+}
+
+fn bytes<I: Iterator<Item = u8>>(mut bits: I, count: usize) -> Vec<u8> {
+    (0..count).map(|_| bits.by_ref().take(8).fold(0, |byte, bit| (byte << 1) | bit)).collect()
+}
+
+fn payload_capacity(carriers: usize, strength: u8) -> usize {
+    let group_len = (1usize << strength) - 1;
+    carriers.saturating_sub(HEADER_LEN * 8) / group_len * strength as usize / 8
+}
+
+fn carrier_count(header: &ImageHeader) -> Result<usize> {
+    Ok(header.width() as usize * header.height() as usize * color_channels(header)?)
+}
+
+fn color_channels(header: &ImageHeader) -> Result<usize> {
+    match header.color_type() {
+        ColorType::Grayscale | ColorType::GrayscaleAlpha => Ok(1),
+        ColorType::Rgb | ColorType::Rgba => Ok(3),
+        ColorType::Indexed => Err(StegoError::UnsupportedColorType(header.color_type()).into())
+    }
+}
+
+/// Byte index and bit shift of the least significant bit of every colour sample, in raster
+/// order or shuffled by the key of `options`.
+fn carrier_positions(header: &ImageHeader, stride: usize, options: &Options) -> Result<Vec<(usize, u8)>> {
+    let bit_depth = header.bit_depth() as usize;
+    let channels = header.color_type().channels() as usize;
+    let color_channels = color_channels(header)?;
+    let width = header.width() as usize;
+
+    let mut order: Vec<usize> = (0..carrier_count(header)?).collect();
+    if let Some(seed) = &options.seed {
+        // Fisher-Yates shuffle
+        let mut keystream = Keystream::new(seed);
+        for i in (1..order.len()).rev() {
+            order.swap(i, (keystream.next_u64() % (i as u64 + 1)) as usize);
+        }
+    }
+
+    Ok(order
+        .into_iter()
+        .map(|sample| {
+            let (pixel, c) = (sample / color_channels, sample % color_channels);
+            let (x, y) = (pixel % width, pixel / width);
+            let bit = y * stride * 8 + (x * channels + c) * bit_depth + bit_depth - 1;
+            (bit / 8, 7 - (bit % 8) as u8)
+        })
+        .collect())
+}
+
+/// SHA-256 in counter mode, enough to shuffle deterministically from a seed.
+struct Keystream {
+    seed: [u8; 32],
+    counter: u64,
+    block: [u8; 32],
+    used: usize
+}
+
+impl Keystream {
+    fn new(seed: &[u8; 32]) -> Self {
+        Self { seed: *seed, counter: 0, block: [0; 32], used: 32 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.used == self.block.len() {
+            let mut hasher = Sha256::new();
+            hasher.update(self.seed);
+            hasher.update(self.counter.to_be_bytes());
+            self.block = hasher.finalize().into();
+            self.counter += 1;
+            self.used = 0;
+        }
+        let value = u64::from_be_bytes(self.block[self.used..self.used + 8].try_into().unwrap());
+        self.used += 8;
+        value
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StegoError {
+    UnsupportedColorType(ColorType),
+    CapacityExceeded { needed: usize, available: usize },
+    /// The pixels do not hold an embedded payload.
+    NoPayload,
+    TagMismatch { expected: String, found: String },
+    InvalidStrength(u8),
+    KeyDerivation(String)
+}
+
+impl Display for StegoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StegoError::UnsupportedColorType(color_type) => {
+                write!(f, "Cannot hide data in the pixels of a {color_type} image")
+            }
+            StegoError::CapacityExceeded { needed, available } => {
+                write!(f, "Payload of {needed} bytes does not fit in the image. Capacity: {available} bytes")
+            }
+            StegoError::NoPayload => {
+                write!(f, "No payload found in the image pixels")
+            }
+            StegoError::TagMismatch { expected, found } => {
+                write!(f, "Pixels hold a {found} payload, not {expected}")
+            }
+            StegoError::InvalidStrength(strength) => {
+                write!(f, "Invalid strength {strength}. Expected 1 to {MAX_STRENGTH}")
+            }
+            StegoError::KeyDerivation(reason) => {
+                write!(f, "Could not derive the embedding order: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StegoError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_header::InterlaceMethod;
+    use crate::Error;
+    use std::str::FromStr;
+
+    fn blank(bit_depth: u8, color_type: ColorType) -> PixelBuffer {
+        let header = ImageHeader::new(20, 10, bit_depth, color_type, InterlaceMethod::None).unwrap();
+        let len = (20 * header.bits_per_pixel()).div_ceil(8) * 10;
+        PixelBuffer::new(header, vec![0xAA; len]).unwrap()
+    }
+
+    fn keyed(seed: u8) -> Options {
+        // Skips Argon2, which is slow in debug builds
+        Options { seed: Some([seed; 32]), strength: 1 }
+    }
+
+    fn tag() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        for (bit_depth, color_type) in [(8, ColorType::Rgb), (16, ColorType::Rgba), (2, ColorType::Grayscale), (8, ColorType::GrayscaleAlpha)] {
+            for options in [Options::default(), keyed(1)] {
+                let mut pixels = blank(bit_depth, color_type);
+                let payload = vec![0x5A; capacity(pixels.header(), &options).unwrap()];
+                embed(&mut pixels, &tag(), &payload, &options).unwrap();
+                assert_eq!(extract(&pixels, &tag(), &options).unwrap(), payload, "{color_type} at {bit_depth} bits");
+            }
+        }
+    }
+
+    #[test]
+    fn test_embed_leaves_alpha_and_high_bits() {
+        let mut pixels = blank(8, ColorType::Rgba);
+        embed(&mut pixels, &tag(), b"hi", &keyed(1)).unwrap();
+        for (i, &byte) in pixels.as_bytes().iter().enumerate() {
+            assert_eq!(byte & 0xFE, 0xAA);
+            if i % 4 == 3 {
+                assert_eq!(byte, 0xAA);
+            }
+        }
+    }
+
+    #[test]
+    fn test_keyed_order() {
+        let mut sequential = blank(8, ColorType::Rgb);
+        embed(&mut sequential, &tag(), b"hello", &Options::default()).unwrap();
+        let mut scattered = blank(8, ColorType::Rgb);
+        embed(&mut scattered, &tag(), b"hello", &keyed(1)).unwrap();
+        assert_ne!(scattered, sequential);
+
+        assert_eq!(extract(&scattered, &tag(), &keyed(1)).unwrap(), b"hello");
+        assert!(matches!(extract(&scattered, &tag(), &keyed(2)), Err(Error::Stego(StegoError::NoPayload))));
+        assert!(matches!(extract(&scattered, &tag(), &Options::default()), Err(Error::Stego(StegoError::NoPayload))));
+    }
+
+    #[test]
+    fn test_matrix_encoding() {
+        let payload: Vec<u8> = (0..20).map(|i| (i * 37 + 11) as u8).collect();
+        let mut changes = Vec::new();
+        for strength in 1..=4 {
+            let options = keyed(3).with_strength(strength).unwrap();
+            let mut pixels = blank(8, ColorType::Rgb);
+            let payload = &payload[..payload.len().min(capacity(pixels.header(), &options).unwrap())];
+            changes.push(embed(&mut pixels, &tag(), payload, &options).unwrap() as f64 / payload.len() as f64);
+
+            // The strength is read back from the header
+            assert_eq!(extract(&pixels, &tag(), &keyed(3)).unwrap(), payload, "strength {strength}");
+        }
+        assert!(changes.windows(2).all(|w| w[1] < w[0]), "changes per payload byte: {changes:?}");
+
+        assert_eq!(capacity(blank(8, ColorType::Rgb).header(), &Options::default().with_strength(3).unwrap()).unwrap(), (600 - HEADER_LEN * 8) / 7 * 3 / 8);
+        assert!(Options::default().with_strength(0).is_err());
+        assert!(Options::default().with_strength(MAX_STRENGTH + 1).is_err());
+    }
+
+    #[test]
+    fn test_capacity_and_errors() {
+        let options = Options::default();
+        let mut pixels = blank(8, ColorType::Rgb);
+        assert_eq!(capacity(pixels.header(), &options).unwrap(), 20 * 10 * 3 / 8 - HEADER_LEN);
+
+        let too_long = vec![0; capacity(pixels.header(), &options).unwrap() + 1];
+        assert!(matches!(embed(&mut pixels, &tag(), &too_long, &options), Err(Error::Stego(StegoError::CapacityExceeded { .. }))));
+        assert!(matches!(extract(&pixels, &tag(), &options), Err(Error::Stego(StegoError::NoPayload))));
+
+        embed(&mut pixels, &tag(), b"hi", &options).unwrap();
+        let other = ChunkType::from_str("abCd").unwrap();
+        assert!(matches!(extract(&pixels, &other, &options), Err(Error::Stego(StegoError::TagMismatch { .. }))));
+
+        assert!(capacity(blank(8, ColorType::Indexed).header(), &options).is_err());
+    }
+
+    #[test]
+    fn test_extract_without_payload() {
+        let options = Options::default();
+        let mut pixels = blank(8, ColorType::Rgb);
+        let mut state = 1u32;
+        for byte in pixels.as_bytes_mut() {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            *byte = (state >> 16) as u8;
+        }
+        assert!(matches!(extract(&pixels, &tag(), &options), Err(Error::Stego(StegoError::NoPayload))));
+
+        // A header with the magic but a tag that is not a chunk type, such as non-UTF-8 bytes
+        let carriers = carrier_positions(pixels.header(), pixels.stride(), &options).unwrap();
+        let header: Vec<u8> = MAGIC.iter().copied().chain([0xFF, 0xFE, b'a', b'b']).collect();
+        let data = pixels.as_bytes_mut();
+        for (bit, (index, shift)) in bits(&header).zip(carriers) {
+            data[index] = (data[index] & !(1 << shift)) | (bit << shift);
+        }
+        assert!(matches!(extract(&pixels, &tag(), &options), Err(Error::Stego(StegoError::NoPayload))));
+
+        let invalid = ChunkType::try_from([0xFF, 0xFE, b'a', b'b']).unwrap();
+        assert!(embed(&mut pixels, &invalid, b"hi", &options).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::chunk::{read_u32, Chunk, ChunkError};
+use crate::png::{ChunkPosition, Occurrence, Png, PngError};
+use crate::{Result, MAX_CHUNK_LEN};
+
+/// Reads a PNG one chunk at a time, checking each chunk's CRC as it goes.
+///
+/// Reading stops after IEND. Any bytes after it are left in the underlying reader, which
+/// [`ChunkReader::into_inner`] returns.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    offset: usize,
+    done: bool,
+    iend: bool
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Reads and checks the PNG signature.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0; 8];
+        if read_full(&mut reader, &mut header)? < header.len() {
+            return Err(PngError::InvalidLength.into());
+        }
+        if header != Png::STANDARD_HEADER {
+            return Err(PngError::IncorrectPngHeader.into());
+        }
+
+        Ok(Self {
+            reader,
+            offset: header.len(),
+            done: false,
+            iend: false
+        })
+    }
+
+    /// Byte offset of the next chunk from the start of the file.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The underlying reader. Once every chunk has been read, what remains is the trailing data.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the next chunk, or `None` after IEND or at the end of the input.
+    pub fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        let offset = self.offset;
+        self.read_chunk_at_offset().map_err(|e| e.at_offset(offset))
+    }
+
+    fn read_chunk_at_offset(&mut self) -> Result<Option<Chunk>> {
+        if self.iend { return Ok(None) }
+
+        let mut header = [0; 8];
+        match read_full(&mut self.reader, &mut header)? {
+            0 => return Ok(None),
+            8 => {}
+            n => return Err(ChunkError::InvalidChunkLength(n).into())
+        }
+
+        let data_length = read_u32(&header[0..4]);
+        if data_length > MAX_CHUNK_LEN { return Err(ChunkError::DataTooLong(data_length as usize).into()) }
+
+        // Let the buffer grow with the input instead of trusting the declared length up front.
+        let mut data = Vec::new();
+        (&mut self.reader).take(data_length as u64).read_to_end(&mut data)?;
+
+        let mut crc = [0; 4];
+        if data.len() < data_length as usize || read_full(&mut self.reader, &mut crc)? < crc.len() {
+            return Err(ChunkError::Truncated { declared: data_length, available: data.len() }.into());
+        }
+
+        let chunk = Chunk::from_parts([header[4], header[5], header[6], header[7]], data, u32::from_be_bytes(crc))?;
+        self.offset += 12 + data_length as usize;
+        self.iend = is_type(&chunk, "IEND");
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done { return None; }
+
+        let chunk = self.read_chunk().transpose();
+        if !matches!(chunk, Some(Ok(_))) {
+            self.done = true;
+        }
+        chunk
+    }
+}
+
+/// Writes the PNG signature followed by chunks, one at a time.
+pub struct ChunkWriter<W: Write> {
+    writer: W
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Writes the PNG signature.
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        self.writer.write_all(&chunk.length().to_be_bytes())?;
+        self.writer.write_all(&chunk.chunk_type().bytes())?;
+        self.writer.write_all(chunk.data())?;
+        self.writer.write_all(&chunk.crc().to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Copies `reader` to the output as is, for the bytes after IEND. Returns the number of bytes copied.
+    pub fn write_trailing_data<R: Read>(&mut self, mut reader: R) -> Result<u64> {
+        Ok(io::copy(&mut reader, &mut self.writer)?)
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Copies every chunk from `reader` to `writer`, inserting `chunk` at `position`.
+/// Trailing data after IEND is copied as is.
+///
+/// Streaming counterpart of [`Png::insert_chunk`].
+pub fn insert_chunk<R: Read, W: Write>(
+    reader: ChunkReader<R>,
+    writer: &mut ChunkWriter<W>,
+    chunk: Chunk,
+    position: ChunkPosition
+) -> Result<()> {
+    insert_chunks(reader, writer, vec![chunk], position)
+}
+
+/// Like [`insert_chunk`], but inserts several consecutive chunks at `position`.
+pub fn insert_chunks<R: Read, W: Write>(
+    mut reader: ChunkReader<R>,
+    writer: &mut ChunkWriter<W>,
+    chunks: Vec<Chunk>,
+    position: ChunkPosition
+) -> Result<()> {
+    let mut pending = Some(chunks);
+    let mut index = 0;
+    let mut seen_idat = false;
+    let mut seen_iend = false;
+
+    for next in reader.by_ref() {
+        let next = next?;
+        let is_idat = is_type(&next, "IDAT");
+
+        let insert_before = match position {
+            ChunkPosition::BeforeIend => is_type(&next, "IEND"),
+            ChunkPosition::BeforeFirstIdat => is_idat,
+            ChunkPosition::AfterLastIdat => seen_idat && !is_idat,
+            ChunkPosition::Index(target) => index == target,
+            ChunkPosition::AfterIhdr => false
+        };
+        if insert_before {
+            if let Some(chunks) = pending.take() {
+                chunks.iter().try_for_each(|c| writer.write_chunk(c))?;
+            }
+        }
+
+        writer.write_chunk(&next)?;
+        seen_idat |= is_idat;
+        seen_iend |= is_type(&next, "IEND");
+        index += 1;
+
+        if position == ChunkPosition::AfterIhdr && is_type(&next, "IHDR") {
+            if let Some(chunks) = pending.take() {
+                chunks.iter().try_for_each(|c| writer.write_chunk(c))?;
+            }
+        }
+    }
+
+    if let Some(chunks) = pending {
+        match position {
+            ChunkPosition::BeforeIend => {}
+            ChunkPosition::AfterLastIdat if seen_idat => {}
+            // Readers stop at IEND, so a chunk written after it could never be read back
+            ChunkPosition::Index(target) if target == index && !seen_iend => {}
+            ChunkPosition::Index(target) => {
+                return Err(PngError::IndexOutOfBounds(target, if seen_iend { index - 1 } else { index }).into())
+            }
+            ChunkPosition::AfterIhdr => return Err(PngError::AnchorNotFound("IHDR").into()),
+            ChunkPosition::BeforeFirstIdat | ChunkPosition::AfterLastIdat => {
+                return Err(PngError::AnchorNotFound("IDAT").into())
+            }
+        }
+        chunks.iter().try_for_each(|c| writer.write_chunk(c))?;
+    }
+
+    writer.write_trailing_data(reader.into_inner())?;
+    Ok(())
+}
+
+/// Copies every chunk from `reader` to `writer` except the first one of `chunk_type`, which is returned.
+///
+/// Streaming counterpart of [`Png::remove_chunk`].
+pub fn remove_chunk<R: Read, W: Write>(
+    reader: ChunkReader<R>,
+    writer: &mut ChunkWriter<W>,
+    chunk_type: &str
+) -> Result<Chunk> {
+    remove_chunks(reader, writer, chunk_type, Occurrence::First).map(|mut removed| removed.remove(0))
+}
+
+/// Copies every chunk from `reader` to `writer` except the chunks of `chunk_type` selected by
+/// `occurrence`, which are returned. Trailing data after IEND is copied as is.
+///
+/// Streaming counterpart of [`Png::remove_chunks`].
+pub fn remove_chunks<R: Read, W: Write>(
+    mut reader: ChunkReader<R>,
+    writer: &mut ChunkWriter<W>,
+    chunk_type: &str,
+    occurrence: Occurrence
+) -> Result<Vec<Chunk>> {
+    let mut count = 0;
+    let mut removed = Vec::new();
+
+    for next in reader.by_ref() {
+        let next = next?;
+        if is_type(&next, chunk_type) {
+            count += 1;
+            if occurrence.matches(count - 1) {
+                removed.push(next);
+                continue;
+            }
+        }
+        writer.write_chunk(&next)?;
+    }
+
+    if removed.is_empty() { return Err(occurrence.not_found(chunk_type, count)) }
+    writer.write_trailing_data(reader.into_inner())?;
+    Ok(removed)
+}
+
+fn is_type(chunk: &Chunk, chunk_type: &str) -> bool {
+    chunk.chunk_type().to_string() == chunk_type
+}
+
+/// Like `read_exact`, but returns how many bytes were read before the end of the input.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into())
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::Error;
+    use std::str::FromStr;
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.bytes().collect())
+    }
+
+    fn testing_png_bytes() -> Vec<u8> {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header"),
+            chunk_from_strings("IDAT", "first"),
+            chunk_from_strings("IDAT", "second"),
+            chunk_from_strings("IEND", ""),
+        ]).as_bytes()
+    }
+
+    fn chunk_types(bytes: &[u8]) -> Vec<String> {
+        ChunkReader::new(bytes)
+            .unwrap()
+            .map(|c| c.unwrap().chunk_type().to_string())
+            .collect()
+    }
+
+    fn insert(position: ChunkPosition) -> Result<Vec<u8>> {
+        let bytes = testing_png_bytes();
+        let mut writer = ChunkWriter::new(Vec::new())?;
+        insert_chunk(ChunkReader::new(bytes.as_slice())?, &mut writer, chunk_from_strings("ruSt", "hi"), position)?;
+        writer.into_inner()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = testing_png_bytes();
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        for chunk in ChunkReader::new(bytes.as_slice()).unwrap() {
+            writer.write_chunk(&chunk.unwrap()).unwrap();
+        }
+        assert_eq!(writer.into_inner().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_reader_offset() {
+        let bytes = testing_png_bytes();
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.offset(), 8);
+        reader.read_chunk().unwrap();
+        assert_eq!(reader.offset(), 8 + 12 + 6);
+    }
+
+    #[test]
+    fn test_reader_truncated() {
+        let bytes = testing_png_bytes();
+        // Cut off inside the data of the second IDAT chunk, which starts at byte 43
+        let truncated = &bytes[..54];
+        let result: Result<Vec<Chunk>> = ChunkReader::new(truncated).unwrap().collect();
+        assert!(matches!(result, Err(Error::Chunk { offset: 43, source: ChunkError::Truncated { declared: 6, available: 3 } })));
+    }
+
+    #[test]
+    fn test_reader_invalid_header() {
+        assert!(ChunkReader::new(&b"not a png"[..]).is_err());
+    }
+
+    #[test]
+    fn test_insert_chunk_positions() {
+        assert_eq!(chunk_types(&insert(ChunkPosition::BeforeIend).unwrap()), ["IHDR", "IDAT", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(chunk_types(&insert(ChunkPosition::AfterIhdr).unwrap()), ["IHDR", "ruSt", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(chunk_types(&insert(ChunkPosition::BeforeFirstIdat).unwrap()), ["IHDR", "ruSt", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(chunk_types(&insert(ChunkPosition::AfterLastIdat).unwrap()), ["IHDR", "IDAT", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(chunk_types(&insert(ChunkPosition::Index(3)).unwrap()), ["IHDR", "IDAT", "IDAT", "ruSt", "IEND"]);
+        // Readers stop at IEND, so a chunk after it would be unreadable trailing data
+        assert!(matches!(insert(ChunkPosition::Index(4)), Err(Error::Png(PngError::IndexOutOfBounds(4, 3)))));
+        assert!(insert(ChunkPosition::Index(5)).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_keeps_trailing_data() {
+        let bytes = [testing_png_bytes(), b"trailing".to_vec()].concat();
+
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        insert_chunk(ChunkReader::new(bytes.as_slice()).unwrap(), &mut writer, chunk_from_strings("ruSt", "hi"), ChunkPosition::BeforeIend).unwrap();
+        let inserted = writer.into_inner().unwrap();
+        assert!(inserted.ends_with(b"IEND\xAEB`\x82trailing"));
+
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        remove_chunk(ChunkReader::new(inserted.as_slice()).unwrap(), &mut writer, "ruSt").unwrap();
+        assert_eq!(writer.into_inner().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_insert_chunk_matches_png() {
+        let mut png = Png::try_from(testing_png_bytes().as_slice()).unwrap();
+        png.insert_chunk(chunk_from_strings("ruSt", "hi"), ChunkPosition::AfterLastIdat).unwrap();
+        assert_eq!(insert(ChunkPosition::AfterLastIdat).unwrap(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_remove_chunk() {
+        let bytes = testing_png_bytes();
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        let removed = remove_chunk(ChunkReader::new(bytes.as_slice()).unwrap(), &mut writer, "IDAT").unwrap();
+        assert_eq!(removed.data(), b"first");
+        assert_eq!(chunk_types(&writer.into_inner().unwrap()), ["IHDR", "IDAT", "IEND"]);
+
+        let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+        assert!(remove_chunk(ChunkReader::new(bytes.as_slice()).unwrap(), &mut writer, "ruSt").is_err());
+    }
+
+    #[test]
+    fn test_remove_chunks() {
+        let bytes = testing_png_bytes();
+        let remove = |occurrence| -> Result<(Vec<Chunk>, Vec<u8>)> {
+            let mut writer = ChunkWriter::new(Vec::new())?;
+            let removed = remove_chunks(ChunkReader::new(bytes.as_slice())?, &mut writer, "IDAT", occurrence)?;
+            Ok((removed, writer.into_inner()?))
+        };
+
+        let (removed, output) = remove(Occurrence::Nth(1)).unwrap();
+        assert_eq!(removed[0].data(), b"second");
+        assert_eq!(chunk_types(&output), ["IHDR", "IDAT", "IEND"]);
+
+        let (removed, output) = remove(Occurrence::All).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(chunk_types(&output), ["IHDR", "IEND"]);
+
+        assert!(matches!(remove(Occurrence::Nth(2)), Err(Error::Png(PngError::OccurrenceNotFound { index: 2, count: 2, .. }))));
+    }
+}
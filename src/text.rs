@@ -0,0 +1,389 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::compress;
+use crate::png::{ChunkPosition, Png};
+use crate::{Error, Result};
+
+/// Chunk types holding text metadata.
+pub const TEXT_CHUNK_TYPES: [&str; 3] = ["tEXt", "zTXt", "iTXt"];
+
+/// Longest text inflated from a zTXt or compressed iTXt chunk.
+pub const MAX_INFLATED_LEN: u64 = 64 * 1024 * 1024;
+
+const MAX_KEYWORD_LEN: usize = 79;
+
+/// A keyword/text pair from a tEXt, zTXt or iTXt chunk.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TextChunk {
+    /// tEXt: uncompressed Latin-1 text.
+    Text { keyword: String, text: String },
+    /// zTXt: deflate-compressed Latin-1 text.
+    Compressed { keyword: String, text: String },
+    /// iTXt: UTF-8 text, optionally compressed, with a language tag and translated keyword.
+    International {
+        keyword: String,
+        compressed: bool,
+        language_tag: String,
+        translated_keyword: String,
+        text: String
+    }
+}
+
+impl TextChunk {
+    /// A tEXt chunk. The text must be Latin-1.
+    pub fn text(keyword: &str, text: &str) -> Result<Self> {
+        check_keyword(keyword)?;
+        check_latin1(text)?;
+        Ok(TextChunk::Text { keyword: keyword.to_string(), text: text.to_string() })
+    }
+
+    /// A zTXt chunk. The text must be Latin-1.
+    pub fn compressed(keyword: &str, text: &str) -> Result<Self> {
+        check_keyword(keyword)?;
+        check_latin1(text)?;
+        Ok(TextChunk::Compressed { keyword: keyword.to_string(), text: text.to_string() })
+    }
+
+    /// An iTXt chunk. `language_tag` may be empty, or hyphen-separated ASCII words such as `en-GB`.
+    pub fn international(
+        keyword: &str,
+        language_tag: &str,
+        translated_keyword: &str,
+        text: &str,
+        compressed: bool
+    ) -> Result<Self> {
+        check_keyword(keyword)?;
+        if !language_tag.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+            return Err(TextError::InvalidLanguageTag(language_tag.to_string()).into());
+        }
+        if translated_keyword.contains('\0') || text.contains('\0') { return Err(TextError::ContainsNul.into()) }
+
+        Ok(TextChunk::International {
+            keyword: keyword.to_string(),
+            compressed,
+            language_tag: language_tag.to_string(),
+            translated_keyword: translated_keyword.to_string(),
+            text: text.to_string()
+        })
+    }
+
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextChunk::Text { keyword, .. }
+            | TextChunk::Compressed { keyword, .. }
+            | TextChunk::International { keyword, .. } => keyword
+        }
+    }
+
+    pub fn text_value(&self) -> &str {
+        match self {
+            TextChunk::Text { text, .. }
+            | TextChunk::Compressed { text, .. }
+            | TextChunk::International { text, .. } => text
+        }
+    }
+
+    pub fn chunk_type(&self) -> ChunkType {
+        let chunk_type = match self {
+            TextChunk::Text { .. } => "tEXt",
+            TextChunk::Compressed { .. } => "zTXt",
+            TextChunk::International { .. } => "iTXt"
+        };
+        ChunkType::from_str(chunk_type).unwrap()
+    }
+
+    /// Whether `chunk_type` is tEXt, zTXt or iTXt.
+    pub fn is_text_type(chunk_type: &ChunkType) -> bool {
+        TEXT_CHUNK_TYPES.contains(&chunk_type.to_string().as_str())
+    }
+
+    /// Parses the data of a text chunk of the given type.
+    pub fn from_data(chunk_type: &ChunkType, data: &[u8]) -> Result<Self> {
+        let (keyword, rest) = split_nul(data).ok_or(TextError::Malformed)?;
+        let keyword = from_latin1(keyword);
+        check_keyword(&keyword)?;
+
+        match chunk_type.to_string().as_str() {
+            "tEXt" => Ok(TextChunk::Text { keyword, text: from_latin1(rest) }),
+            "zTXt" => {
+                let (&method, compressed) = rest.split_first().ok_or(TextError::Malformed)?;
+                if method != 0 { return Err(TextError::UnsupportedCompression(method).into()) }
+                Ok(TextChunk::Compressed { keyword, text: from_latin1(&inflate(compressed)?) })
+            }
+            "iTXt" => {
+                if rest.len() < 2 { return Err(TextError::Malformed.into()) }
+                let (flag, method) = (rest[0], rest[1]);
+                if flag > 1 { return Err(TextError::Malformed.into()) }
+                if flag == 1 && method != 0 { return Err(TextError::UnsupportedCompression(method).into()) }
+
+                let (language_tag, rest) = split_nul(&rest[2..]).ok_or(TextError::Malformed)?;
+                let (translated_keyword, text) = split_nul(rest).ok_or(TextError::Malformed)?;
+                let text = if flag == 1 { inflate(text)? } else { text.to_vec() };
+
+                Ok(TextChunk::International {
+                    keyword,
+                    compressed: flag == 1,
+                    language_tag: std::str::from_utf8(language_tag)?.to_string(),
+                    translated_keyword: std::str::from_utf8(translated_keyword)?.to_string(),
+                    text: String::from_utf8(text).map_err(|e| e.utf8_error())?
+                })
+            }
+            _ => Err(TextError::NotText(chunk_type.to_string()).into())
+        }
+    }
+
+    /// Serialized chunk data. zTXt and compressed iTXt text is deflated with [`compress::deflate`].
+    pub fn to_data(&self) -> Result<Vec<u8>> {
+        let mut data = to_latin1(self.keyword());
+        data.push(0);
+
+        match self {
+            TextChunk::Text { text, .. } => data.extend(to_latin1(text)),
+            TextChunk::Compressed { text, .. } => {
+                data.push(0);
+                data.extend(compress::deflate(&to_latin1(text))?);
+            }
+            TextChunk::International { compressed, language_tag, translated_keyword, text, .. } => {
+                data.extend([*compressed as u8, 0]);
+                data.extend(language_tag.as_bytes());
+                data.push(0);
+                data.extend(translated_keyword.as_bytes());
+                data.push(0);
+                if *compressed {
+                    data.extend(compress::deflate(text.as_bytes())?);
+                } else {
+                    data.extend(text.as_bytes());
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        Ok(Chunk::try_new(self.chunk_type(), self.to_data()?)?)
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = Error;
+
+    fn try_from(value: &Chunk) -> Result<Self> {
+        TextChunk::from_data(value.chunk_type(), value.data())
+    }
+}
+
+impl Display for TextChunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.chunk_type(), self.keyword())?;
+        if let TextChunk::International { language_tag, translated_keyword, .. } = self {
+            if !language_tag.is_empty() {
+                write!(f, " [{language_tag}]")?;
+            }
+            if !translated_keyword.is_empty() {
+                write!(f, " ({translated_keyword})")?;
+            }
+        }
+        write!(f, ": {}", self.text_value())
+    }
+}
+
+/// Every text chunk in `png` with its chunk index, in file order.
+pub fn text_chunks(png: &Png) -> Vec<(usize, Result<TextChunk>)> {
+    png.chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| TextChunk::is_text_type(c.chunk_type()))
+        .map(|(index, c)| (index, TextChunk::try_from(c)))
+        .collect()
+}
+
+/// The first text chunk with `keyword`.
+pub fn get_text(png: &Png, keyword: &str) -> Result<TextChunk> {
+    text_chunks(png)
+        .into_iter()
+        .filter_map(|(_, text)| text.ok())
+        .find(|text| text.keyword() == keyword)
+        .ok_or_else(|| TextError::KeywordNotFound(keyword.to_string()).into())
+}
+
+/// Replaces the text chunks with the same keyword by `text`, or inserts it before `IEND`.
+pub fn set_text(png: &mut Png, text: TextChunk) -> Result<()> {
+    let chunk = text.to_chunk()?;
+    let position = match remove_text(png, text.keyword()) {
+        Ok(index) => ChunkPosition::Index(index),
+        Err(_) => ChunkPosition::BeforeIend
+    };
+    png.insert_chunk(chunk, position)
+}
+
+/// Removes every text chunk with `keyword`, returning the index of the first one.
+pub fn remove_text(png: &mut Png, keyword: &str) -> Result<usize> {
+    let first = text_chunks(png)
+        .into_iter()
+        .find(|(_, text)| matches!(text, Ok(text) if text.keyword() == keyword))
+        .map(|(index, _)| index)
+        .ok_or_else(|| TextError::KeywordNotFound(keyword.to_string()))?;
+
+    png.retain_chunks(|c| !matches!(TextChunk::try_from(c), Ok(text) if text.keyword() == keyword));
+    Ok(first)
+}
+
+/// Checks a keyword: 1-79 printable Latin-1 characters, no leading, trailing or consecutive spaces.
+pub fn check_keyword(keyword: &str) -> Result<()> {
+    let invalid = |reason| Err(TextError::InvalidKeyword { keyword: keyword.to_string(), reason }.into());
+
+    let length = keyword.chars().count();
+    if length == 0 || length > MAX_KEYWORD_LEN { return invalid("must be 1-79 characters long") }
+    if keyword.chars().any(|c| !matches!(c, ' '..='~' | '\u{A1}'..='\u{FF}')) {
+        return invalid("must be printable Latin-1");
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') { return invalid("must not start or end with a space") }
+    if keyword.contains("  ") { return invalid("must not contain consecutive spaces") }
+    Ok(())
+}
+
+fn check_latin1(text: &str) -> Result<()> {
+    if text.contains('\0') { return Err(TextError::ContainsNul.into()) }
+    match text.chars().find(|&c| c > '\u{FF}') {
+        Some(c) => Err(TextError::NotLatin1(c).into()),
+        None => Ok(())
+    }
+}
+
+fn split_nul(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let nul = data.iter().position(|&b| b == 0)?;
+    Some((&data[..nul], &data[nul + 1..]))
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    let text = compress::inflate(data, MAX_INFLATED_LEN)?;
+    if text.len() as u64 > MAX_INFLATED_LEN { return Err(TextError::TooLong.into()) }
+    Ok(text)
+}
+
+fn from_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Callers check the text with [`check_latin1`] first.
+fn to_latin1(text: &str) -> Vec<u8> {
+    text.chars().map(|c| c as u8).collect()
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TextError {
+    InvalidKeyword { keyword: String, reason: &'static str },
+    NotLatin1(char),
+    ContainsNul,
+    InvalidLanguageTag(String),
+    UnsupportedCompression(u8),
+    Malformed,
+    TooLong,
+    NotText(String),
+    KeywordNotFound(String)
+}
+
+impl Display for TextError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextError::InvalidKeyword { keyword, reason } => {
+                write!(f, "Invalid keyword {keyword:?}: {reason}")
+            }
+            TextError::NotLatin1(c) => {
+                write!(f, "Character {c:?} is not Latin-1. Use an iTXt chunk for UTF-8 text")
+            }
+            TextError::ContainsNul => {
+                write!(f, "Text must not contain NUL characters")
+            }
+            TextError::InvalidLanguageTag(tag) => {
+                write!(f, "Invalid language tag: {tag:?}")
+            }
+            TextError::UnsupportedCompression(method) => {
+                write!(f, "Unsupported text compression method: {method}")
+            }
+            TextError::Malformed => {
+                write!(f, "Text chunk is malformed")
+            }
+            TextError::TooLong => {
+                write!(f, "Compressed text is longer than {MAX_INFLATED_LEN} bytes")
+            }
+            TextError::NotText(chunk_type) => {
+                write!(f, "{chunk_type} is not a text chunk")
+            }
+            TextError::KeywordNotFound(keyword) => {
+                write!(f, "No text chunk with keyword {keyword:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Title\0Dice".to_vec()),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_text_round_trips() {
+        let texts = [
+            TextChunk::text("Title", "Caf\u{e9}").unwrap(),
+            TextChunk::compressed("Comment", &"long ".repeat(100)).unwrap(),
+            TextChunk::international("Title", "lt", "Pavadinimas", "Kauliukai \u{1F3B2}", false).unwrap(),
+            TextChunk::international("Title", "", "", "\u{17e}odis", true).unwrap(),
+        ];
+        for text in texts {
+            let chunk = text.to_chunk().unwrap();
+            assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn test_text_latin1_encoding() {
+        let chunk = TextChunk::text("Author", "Jos\u{e9}").unwrap().to_chunk().unwrap();
+        assert_eq!(chunk.data(), b"Author\0Jos\xe9");
+        assert!(matches!(TextChunk::text("Title", "\u{1F3B2}"), Err(Error::Text(TextError::NotLatin1(_)))));
+    }
+
+    #[test]
+    fn test_keyword_validation() {
+        assert!(check_keyword("Title").is_ok());
+        assert!(check_keyword("Creation Time").is_ok());
+        assert!(check_keyword(&"k".repeat(79)).is_ok());
+
+        for keyword in ["", " Title", "Title ", "Two  spaces", "Tab\t", "\u{17e}odis"] {
+            assert!(check_keyword(keyword).is_err(), "{keyword:?}");
+        }
+        assert!(check_keyword(&"k".repeat(80)).is_err());
+    }
+
+    #[test]
+    fn test_set_get_remove() {
+        let mut png = testing_png();
+        assert_eq!(get_text(&png, "Title").unwrap().text_value(), "Dice");
+
+        set_text(&mut png, TextChunk::compressed("Title", "Red dice").unwrap()).unwrap();
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "zTXt");
+        assert_eq!(get_text(&png, "Title").unwrap().text_value(), "Red dice");
+
+        set_text(&mut png, TextChunk::text("Author", "Ignas").unwrap()).unwrap();
+        assert_eq!(png.chunks()[3].chunk_type().to_string(), "tEXt");
+        assert_eq!(text_chunks(&png).len(), 2);
+
+        assert_eq!(remove_text(&mut png, "Title").unwrap(), 1);
+        assert!(matches!(get_text(&png, "Title"), Err(Error::Text(TextError::KeywordNotFound(_)))));
+        assert_eq!(png.chunks().len(), 4);
+    }
+}
@@ -0,0 +1,366 @@
+use std::error::Error as _;
+use std::fmt::{Display, Formatter};
+
+use crate::chunk::ChunkRef;
+use crate::chunk_type::ChunkType;
+use crate::image_header::ColorType;
+use crate::png::Png;
+
+/// A broken PNG ordering or multiplicity rule, as reported by [`Png::validate`].
+///
+/// `index` is the position of the offending chunk in [`Png::chunks`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Violation {
+    MissingChunk(&'static str),
+    /// The chunk may appear at most once.
+    DuplicateChunk { chunk_type: ChunkType, index: usize },
+    /// The chunk must come before all chunks of type `before`.
+    MustPrecede { chunk_type: ChunkType, index: usize, before: &'static str },
+    /// The chunk must come after the chunk of type `after`.
+    MustFollow { chunk_type: ChunkType, index: usize, after: &'static str },
+    IhdrNotFirst { index: usize },
+    NonContiguousIdat { index: usize },
+    /// Chunks found in the trailing data continue the numbering past the last chunk.
+    ChunkAfterIend { chunk_type: ChunkType, index: usize },
+    /// Bytes after IEND that are not chunks.
+    TrailingData { length: usize },
+    /// A critical chunk this crate does not know, which decoders must reject.
+    UnknownCriticalChunk { chunk_type: ChunkType, index: usize },
+    /// PLTE is required for indexed images and forbidden for greyscale ones.
+    PaletteRequired,
+    PaletteForbidden { index: usize },
+    InvalidPaletteLength { index: usize, length: u32 },
+    /// hIST only makes sense alongside a palette.
+    HistogramWithoutPalette { index: usize },
+    /// iCCP and sRGB both describe the colour space and should not appear together.
+    IccpWithSrgb,
+    NonEmptyIend { index: usize, length: u32 },
+    InvalidImageHeader(String)
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::MissingChunk(chunk_type) => {
+                write!(f, "Missing required {chunk_type} chunk")
+            }
+            Violation::DuplicateChunk { chunk_type, index } => {
+                write!(f, "Chunk {index}: {chunk_type} may only appear once")
+            }
+            Violation::MustPrecede { chunk_type, index, before } => {
+                write!(f, "Chunk {index}: {chunk_type} must come before {before}")
+            }
+            Violation::MustFollow { chunk_type, index, after } => {
+                write!(f, "Chunk {index}: {chunk_type} must come after {after}")
+            }
+            Violation::IhdrNotFirst { index } => {
+                write!(f, "Chunk {index}: IHDR must be the first chunk")
+            }
+            Violation::NonContiguousIdat { index } => {
+                write!(f, "Chunk {index}: IDAT chunks must be consecutive")
+            }
+            Violation::ChunkAfterIend { chunk_type, index } => {
+                write!(f, "Chunk {index}: {chunk_type} appears after IEND")
+            }
+            Violation::TrailingData { length } => {
+                write!(f, "{length} bytes of data after IEND")
+            }
+            Violation::UnknownCriticalChunk { chunk_type, index } => {
+                write!(f, "Chunk {index}: unknown critical chunk {chunk_type}")
+            }
+            Violation::PaletteRequired => {
+                write!(f, "Indexed-colour images require a PLTE chunk")
+            }
+            Violation::PaletteForbidden { index } => {
+                write!(f, "Chunk {index}: PLTE is not allowed in greyscale images")
+            }
+            Violation::InvalidPaletteLength { index, length } => {
+                write!(f, "Chunk {index}: PLTE length {length} is not a multiple of 3 between 3 and 768")
+            }
+            Violation::HistogramWithoutPalette { index } => {
+                write!(f, "Chunk {index}: hIST requires a PLTE chunk")
+            }
+            Violation::IccpWithSrgb => {
+                write!(f, "iCCP and sRGB should not both be present")
+            }
+            Violation::NonEmptyIend { index, length } => {
+                write!(f, "Chunk {index}: IEND must be empty, found {length} bytes")
+            }
+            Violation::InvalidImageHeader(message) => {
+                write!(f, "Invalid IHDR: {message}")
+            }
+        }
+    }
+}
+
+/// Where a known chunk may appear relative to the critical chunks.
+enum Placement {
+    Anywhere,
+    BeforePlteAndIdat,
+    AfterPlteBeforeIdat,
+    BeforeIdat
+}
+
+struct Rule {
+    chunk_type: &'static str,
+    multiple: bool,
+    placement: Placement
+}
+
+const fn rule(chunk_type: &'static str, multiple: bool, placement: Placement) -> Rule {
+    Rule { chunk_type, multiple, placement }
+}
+
+/// Ordering and multiplicity of the chunk types defined by the PNG specification.
+/// IHDR, IDAT and IEND have dedicated checks.
+const RULES: [Rule; 19] = [
+    rule("PLTE", false, Placement::BeforeIdat),
+    rule("cHRM", false, Placement::BeforePlteAndIdat),
+    rule("cICP", false, Placement::BeforePlteAndIdat),
+    rule("gAMA", false, Placement::BeforePlteAndIdat),
+    rule("iCCP", false, Placement::BeforePlteAndIdat),
+    rule("mDCV", false, Placement::BeforePlteAndIdat),
+    rule("cLLI", false, Placement::BeforePlteAndIdat),
+    rule("sBIT", false, Placement::BeforePlteAndIdat),
+    rule("sRGB", false, Placement::BeforePlteAndIdat),
+    rule("bKGD", false, Placement::AfterPlteBeforeIdat),
+    rule("hIST", false, Placement::AfterPlteBeforeIdat),
+    rule("tRNS", false, Placement::AfterPlteBeforeIdat),
+    rule("eXIf", false, Placement::BeforeIdat),
+    rule("pHYs", false, Placement::BeforeIdat),
+    rule("sPLT", true, Placement::BeforeIdat),
+    rule("tIME", false, Placement::Anywhere),
+    rule("tEXt", true, Placement::Anywhere),
+    rule("zTXt", true, Placement::Anywhere),
+    rule("iTXt", true, Placement::Anywhere),
+];
+
+/// Whether the PNG specification defines this chunk type.
+pub(crate) fn is_known(chunk_type: &str) -> bool {
+    matches!(chunk_type, "IHDR" | "IDAT" | "IEND") || RULES.iter().any(|r| r.chunk_type == chunk_type)
+}
+
+pub(crate) fn validate(png: &Png) -> Vec<Violation> {
+    let chunks = png.chunks();
+    let types: Vec<String> = chunks.iter().map(|c| c.chunk_type().to_string()).collect();
+    let position = |name: &str| types.iter().position(|t| t == name);
+    let first_idat = position("IDAT");
+    let plte = position("PLTE");
+    let iend = position("IEND");
+
+    let mut violations = Vec::new();
+
+    match png.image_header() {
+        Ok(header) => match (header.color_type(), plte) {
+            (ColorType::Indexed, None) => violations.push(Violation::PaletteRequired),
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(index)) => {
+                violations.push(Violation::PaletteForbidden { index })
+            }
+            _ => {}
+        },
+        Err(e) if position("IHDR") == Some(0) => {
+            let message = e.source().map_or_else(|| e.to_string(), ToString::to_string);
+            violations.push(Violation::InvalidImageHeader(message))
+        }
+        Err(_) => {}
+    }
+
+    for required in ["IHDR", "IDAT", "IEND"] {
+        if position(required).is_none() {
+            violations.push(Violation::MissingChunk(required));
+        }
+    }
+
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+        let name = types[index].as_str();
+
+        if iend.is_some_and(|iend| index > iend) {
+            violations.push(Violation::ChunkAfterIend { chunk_type: chunk_type.clone(), index });
+        }
+
+        let seen_before = types[..index].iter().any(|t| t == name);
+
+        match name {
+            "IHDR" => {
+                if seen_before {
+                    violations.push(Violation::DuplicateChunk { chunk_type: chunk_type.clone(), index });
+                } else if index != 0 {
+                    violations.push(Violation::IhdrNotFirst { index });
+                }
+                continue;
+            }
+            "IDAT" => {
+                if index > 0 && seen_before && types[index - 1] != "IDAT" {
+                    violations.push(Violation::NonContiguousIdat { index });
+                }
+                continue;
+            }
+            "IEND" => {
+                if seen_before {
+                    violations.push(Violation::DuplicateChunk { chunk_type: chunk_type.clone(), index });
+                }
+                if chunk.length() != 0 {
+                    violations.push(Violation::NonEmptyIend { index, length: chunk.length() });
+                }
+                continue;
+            }
+            "PLTE" => {
+                let length = chunk.length();
+                if length == 0 || length % 3 != 0 || length > 768 {
+                    violations.push(Violation::InvalidPaletteLength { index, length });
+                }
+            }
+            "hIST" if plte.is_none() => {
+                violations.push(Violation::HistogramWithoutPalette { index });
+            }
+            _ => {}
+        }
+
+        let Some(rule) = RULES.iter().find(|r| r.chunk_type == name) else {
+            if chunk_type.is_critical() {
+                violations.push(Violation::UnknownCriticalChunk { chunk_type: chunk_type.clone(), index });
+            }
+            continue;
+        };
+
+        if !rule.multiple && seen_before {
+            violations.push(Violation::DuplicateChunk { chunk_type: chunk_type.clone(), index });
+        }
+
+        let before_idat = matches!(rule.placement, Placement::BeforeIdat | Placement::BeforePlteAndIdat | Placement::AfterPlteBeforeIdat);
+        if before_idat && first_idat.is_some_and(|idat| index > idat) {
+            violations.push(Violation::MustPrecede { chunk_type: chunk_type.clone(), index, before: "IDAT" });
+        }
+        match rule.placement {
+            Placement::BeforePlteAndIdat if plte.is_some_and(|plte| index > plte) => {
+                violations.push(Violation::MustPrecede { chunk_type: chunk_type.clone(), index, before: "PLTE" });
+            }
+            Placement::AfterPlteBeforeIdat if plte.is_some_and(|plte| index < plte) => {
+                violations.push(Violation::MustFollow { chunk_type: chunk_type.clone(), index, after: "PLTE" });
+            }
+            _ => {}
+        }
+    }
+
+    if position("iCCP").is_some() && position("sRGB").is_some() {
+        violations.push(Violation::IccpWithSrgb);
+    }
+
+    // Parsing stops at IEND, so chunks written after it are only found in the trailing data
+    let trailing = png.trailing_data();
+    let (mut cursor, mut index) = (0, chunks.len());
+    while let Ok(chunk) = ChunkRef::try_from(&trailing[cursor..]) {
+        violations.push(Violation::ChunkAfterIend { chunk_type: chunk.chunk_type().clone(), index });
+        cursor += 12 + chunk.data().len();
+        index += 1;
+    }
+    if cursor < trailing.len() {
+        violations.push(Violation::TrailingData { length: trailing.len() - cursor });
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::image_header::{ImageHeader, InterlaceMethod};
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn ihdr(color_type: ColorType) -> Chunk {
+        ImageHeader::new(1, 1, 8, color_type, InterlaceMethod::None).unwrap().to_chunk()
+    }
+
+    fn png(chunks: Vec<Chunk>) -> Png {
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_valid_png() {
+        let png = png(vec![
+            ihdr(ColorType::Indexed),
+            chunk("gAMA", &[0, 0, 177, 143]),
+            chunk("PLTE", &[0, 0, 0]),
+            chunk("tRNS", &[0]),
+            chunk("IDAT", b"a"),
+            chunk("IDAT", b"b"),
+            chunk("tEXt", b"k\0v"),
+            chunk("ruSt", b"private"),
+            chunk("IEND", &[]),
+        ]);
+        assert_eq!(png.validate(), vec![]);
+    }
+
+    #[test]
+    fn test_missing_critical_chunks() {
+        let violations = png(vec![chunk("tEXt", b"k\0v")]).validate();
+        assert_eq!(violations, vec![
+            Violation::MissingChunk("IHDR"),
+            Violation::MissingChunk("IDAT"),
+            Violation::MissingChunk("IEND"),
+        ]);
+    }
+
+    #[test]
+    fn test_ordering_violations() {
+        let violations = png(vec![
+            ihdr(ColorType::Rgb),
+            chunk("IDAT", b"a"),
+            chunk("PLTE", &[0, 0, 0]),
+            chunk("IDAT", b"b"),
+            chunk("IHDR", &[]),
+            chunk("IEND", &[]),
+            chunk("tEXt", b"k\0v"),
+        ]).validate();
+        let ihdr_type = ChunkType::from_str("IHDR").unwrap();
+        assert_eq!(violations, vec![
+            Violation::MustPrecede { chunk_type: ChunkType::from_str("PLTE").unwrap(), index: 2, before: "IDAT" },
+            Violation::NonContiguousIdat { index: 3 },
+            Violation::DuplicateChunk { chunk_type: ihdr_type, index: 4 },
+            Violation::ChunkAfterIend { chunk_type: ChunkType::from_str("tEXt").unwrap(), index: 6 },
+        ]);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut png = png(vec![ihdr(ColorType::Rgb), chunk("IDAT", b"a"), chunk("IEND", &[])]);
+        png.set_trailing_data([chunk("teSt", b"after").as_bytes(), b"garbage".to_vec()].concat());
+        assert_eq!(png.validate(), vec![
+            Violation::ChunkAfterIend { chunk_type: ChunkType::from_str("teSt").unwrap(), index: 3 },
+            Violation::TrailingData { length: 7 },
+        ]);
+    }
+
+    #[test]
+    fn test_palette_rules() {
+        let violations = png(vec![ihdr(ColorType::Indexed), chunk("IDAT", b"a"), chunk("IEND", &[])]).validate();
+        assert_eq!(violations, vec![Violation::PaletteRequired]);
+
+        let violations = png(vec![
+            ihdr(ColorType::Grayscale),
+            chunk("PLTE", &[0, 0]),
+            chunk("sRGB", &[0]),
+            chunk("IDAT", b"a"),
+            chunk("IEND", &[1]),
+        ]).validate();
+        assert_eq!(violations, vec![
+            Violation::PaletteForbidden { index: 1 },
+            Violation::InvalidPaletteLength { index: 1, length: 2 },
+            Violation::MustPrecede { chunk_type: ChunkType::from_str("sRGB").unwrap(), index: 2, before: "PLTE" },
+            Violation::NonEmptyIend { index: 4, length: 1 },
+        ]);
+    }
+
+    #[test]
+    fn test_unknown_critical_chunk() {
+        let violations = png(vec![ihdr(ColorType::Rgb), chunk("RuSt", b""), chunk("IDAT", b"a"), chunk("IEND", &[])]).validate();
+        assert_eq!(violations, vec![Violation::UnknownCriticalChunk { chunk_type: ChunkType::from_str("RuSt").unwrap(), index: 1 }]);
+    }
+}